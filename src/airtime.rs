@@ -0,0 +1,81 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+// ARIB STD-T108 allows 360 s of transmission in any hour, the module enforces it with EVENT 32
+pub const TRANSMISSION_LIMIT: Duration = Duration::from_secs(360);
+const WINDOW: Duration = Duration::from_secs(3600);
+// left for what the module sends by itself (PANA, neighbor discovery) and for retries
+const RESERVE: Duration = Duration::from_secs(36);
+
+// 100 kbps of the 920MHz band
+const BITS_PER_SEC: u64 = 100_000;
+// PHY, MAC, 6LoWPAN and UDP headers and the MIC around the UDP payload, roughly
+const FRAME_OVERHEAD_BYTES: usize = 60;
+
+// time on air of a UDP payload
+pub fn airtime(payload_len: usize) -> Duration {
+    Duration::from_micros((payload_len + FRAME_OVERHEAD_BYTES) as u64 * 8 * 1_000_000 / BITS_PER_SEC)
+}
+
+// estimate of the transmission time spent in the last hour
+#[derive(Debug, Default)]
+pub struct AirtimeBudget {
+    sent: VecDeque<(Instant, Duration)>,
+}
+
+impl AirtimeBudget {
+    pub fn record(&mut self, at: Instant, airtime: Duration) {
+        self.sent.push_back((at, airtime));
+    }
+
+    fn expire(&mut self, now: Instant) {
+        while let Some((at, _)) = self.sent.front() {
+            if now.saturating_duration_since(*at) < WINDOW {
+                break;
+            }
+            self.sent.pop_front();
+        }
+    }
+
+    pub fn used(&mut self, now: Instant) -> Duration {
+        self.expire(now);
+        self.sent.iter().map(|(_, airtime)| *airtime).sum()
+    }
+
+    pub fn remaining(&mut self, now: Instant) -> Duration {
+        TRANSMISSION_LIMIT.saturating_sub(self.used(now))
+    }
+
+    // whether sending `airtime` more keeps the reserve untouched
+    pub fn allows(&mut self, now: Instant, airtime: Duration) -> bool {
+        self.used(now) + airtime + RESERVE <= TRANSMISSION_LIMIT
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_airtime() {
+        // Get of E7: 14 bytes of ECHONET Lite
+        assert_eq!(airtime(14), Duration::from_micros(5920));
+    }
+
+    #[test]
+    fn test_budget() {
+        let start = Instant::now();
+        let mut budget = AirtimeBudget::default();
+        assert_eq!(budget.remaining(start), TRANSMISSION_LIMIT);
+
+        budget.record(start, Duration::from_secs(300));
+        assert_eq!(budget.remaining(start), Duration::from_secs(60));
+        assert!(budget.allows(start, Duration::from_secs(24)));
+        assert!(!budget.allows(start, Duration::from_secs(25)));
+
+        // an hour later it is forgotten
+        let later = start + WINDOW;
+        assert_eq!(budget.used(later), Duration::ZERO);
+        assert!(budget.allows(later, Duration::from_secs(25)));
+    }
+}
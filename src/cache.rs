@@ -0,0 +1,420 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use prometheus_exporter::prometheus::{self, core::{Collector, Desc}, proto::MetricFamily, CounterVec, GaugeVec, Opts};
+
+use crate::metrics::Naming;
+
+// what a scrape gets for a value the meter has not refreshed for too long
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum StaleValue {
+    // the series disappears
+    Omit,
+    Nan,
+}
+
+impl FromStr for StaleValue {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "omit" => Ok(StaleValue::Omit),
+            "nan" => Ok(StaleValue::Nan),
+            _ => Err(format!("unknown stale value: {}", s).into()),
+        }
+    }
+}
+
+// a value is stale once it is older than `intervals` of its refresh interval
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Staleness {
+    pub intervals: u32,
+    pub value: StaleValue,
+}
+
+#[derive(Debug)]
+struct Sample {
+    value: f64,
+    updated: Instant,
+}
+
+#[derive(Debug)]
+struct Family {
+    help: String,
+    labels: Vec<String>,
+    // a total the meter counts up, e.g. the cumulative energy
+    counter: bool,
+    // how often the value is read, `None` if it never goes stale
+    interval: Option<Duration>,
+    // by label values
+    samples: BTreeMap<Vec<String>, Sample>,
+}
+
+// the latest values read from the smartmeter.
+// Scrapes are answered from here and never wait for the radio.
+#[derive(Debug, Clone, Default)]
+pub struct ValueCache {
+    families: Arc<Mutex<BTreeMap<String, Family>>>,
+    staleness: Option<Staleness>,
+    naming: Naming,
+}
+
+impl ValueCache {
+    pub fn new(staleness: Option<Staleness>, naming: Naming) -> ValueCache {
+        ValueCache {
+            families: Default::default(),
+            staleness,
+            naming,
+        }
+    }
+
+    pub fn register(staleness: Option<Staleness>, naming: Naming) -> ValueCache {
+        let cache = ValueCache::new(staleness, naming);
+        prometheus::register(Box::new(cache.clone())).expect("can not register the value cache");
+        cache
+    }
+
+    pub fn gauge(&self, name: &str, help: &str) -> CachedGauge {
+        self.gauge_vec(name, help, &[]).with_label_values(&[])
+    }
+
+    pub fn gauge_vec(&self, name: &str, help: &str, labels: &[&str]) -> CachedGaugeVec {
+        self.family(name, help, labels, false)
+    }
+
+    pub fn counter_vec(&self, name: &str, help: &str, labels: &[&str]) -> CachedGaugeVec {
+        self.family(name, help, labels, true)
+    }
+
+    fn family(&self, name: &str, help: &str, labels: &[&str], counter: bool) -> CachedGaugeVec {
+        self.families.lock().expect("failed to acuire lock").entry(name.to_string()).or_insert_with(|| Family {
+            help: help.to_string(),
+            labels: labels.iter().map(|label| label.to_string()).collect(),
+            counter,
+            interval: None,
+            samples: BTreeMap::new(),
+        });
+        CachedGaugeVec {
+            cache: self.clone(),
+            name: name.to_string(),
+        }
+    }
+
+    fn set(&self, name: &str, labels: &[String], value: f64, updated: Instant) {
+        if let Some(family) = self.families.lock().expect("failed to acuire lock").get_mut(name) {
+            family.samples.insert(labels.to_vec(), Sample {
+                value,
+                updated,
+            });
+        }
+    }
+
+    fn set_interval(&self, name: &str, interval: Duration) {
+        if let Some(family) = self.families.lock().expect("failed to acuire lock").get_mut(name) {
+            family.interval = Some(interval);
+        }
+    }
+
+    fn reset(&self, name: &str) {
+        if let Some(family) = self.families.lock().expect("failed to acuire lock").get_mut(name) {
+            family.samples.clear();
+        }
+    }
+
+    fn collect_at(&self, now: Instant) -> Vec<MetricFamily> {
+        let families = self.families.lock().expect("failed to acuire lock");
+        let mut mfs = vec![];
+        for (name, family) in families.iter() {
+            let labels: Vec<&str> = family.labels.iter().map(String::as_str).collect();
+            let opts = self.naming.opts(name, &family.help);
+            let max_age = family.interval.zip(self.staleness).map(|(interval, staleness)| (interval * staleness.intervals, staleness.value));
+            let samples = family.samples.iter().filter_map(|(values, sample)| {
+                let value = match max_age {
+                    // a counter can not be NaN
+                    Some((max_age, stale_value)) if now.saturating_duration_since(sample.updated) > max_age => match stale_value {
+                        StaleValue::Nan if !family.counter => f64::NAN,
+                        _ => return None,
+                    },
+                    _ => sample.value,
+                };
+                Some((values.iter().map(String::as_str).collect::<Vec<&str>>(), value))
+            });
+            if family.counter {
+                let vec = match CounterVec::new(opts, &labels) {
+                    Ok(vec) => vec,
+                    Err(_) => continue,
+                };
+                for (values, value) in samples {
+                    vec.with_label_values(&values).inc_by(value.max(0.0));
+                }
+                mfs.extend(vec.collect());
+            } else {
+                let vec = match GaugeVec::new(opts, &labels) {
+                    Ok(vec) => vec,
+                    Err(_) => continue,
+                };
+                for (values, value) in samples {
+                    vec.with_label_values(&values).set(value);
+                }
+                mfs.extend(vec.collect());
+            }
+        }
+        mfs
+    }
+}
+
+impl Collector for ValueCache {
+    // the families are added as the meter turns out to have them.
+    // The registry takes only one collector without descriptors, this one.
+    fn desc(&self) -> Vec<&Desc> {
+        vec![]
+    }
+
+    fn collect(&self) -> Vec<MetricFamily> {
+        self.collect_at(Instant::now())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CachedGaugeVec {
+    cache: ValueCache,
+    name: String,
+}
+
+impl CachedGaugeVec {
+    pub fn with_label_values(&self, labels: &[&str]) -> CachedGauge {
+        CachedGauge {
+            cache: self.cache.clone(),
+            name: self.name.clone(),
+            labels: labels.iter().map(|label| label.to_string()).collect(),
+        }
+    }
+
+    pub fn reset(&self) {
+        self.cache.reset(&self.name);
+    }
+
+    // for the staleness
+    pub fn refreshed_every(&self, interval: Duration) {
+        self.cache.set_interval(&self.name, interval);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CachedGauge {
+    cache: ValueCache,
+    name: String,
+    labels: Vec<String>,
+}
+
+impl CachedGauge {
+    pub fn set(&self, value: f64) {
+        self.cache.set(&self.name, &self.labels, value, Instant::now());
+    }
+
+    // a value of the past, e.g. a fixed-time reading, goes stale counting from then
+    pub fn set_as_of(&self, value: f64, updated: Instant) {
+        self.cache.set(&self.name, &self.labels, value, updated);
+    }
+
+    pub fn refreshed_every(&self, interval: Duration) {
+        self.cache.set_interval(&self.name, interval);
+    }
+}
+
+// by label values and unix time
+type TimestampedSamples = BTreeMap<(Vec<String>, i64), Sample>;
+
+// values of a past time exported with that time, e.g. fixed-time readings recovered from the meter's history.
+// Each is kept for `retention` after it was recorded, so that every scraper gets it.
+// A series may appear several times in one scrape, in ascending order of the time. Prometheus only stores
+// samples older than its head block with `storage.tsdb.out_of_order_time_window`.
+#[derive(Debug, Clone)]
+pub struct TimestampedGaugeVec {
+    desc: Desc,
+    opts: Opts,
+    labels: Vec<String>,
+    retention: Duration,
+    samples: Arc<Mutex<TimestampedSamples>>,
+}
+
+impl TimestampedGaugeVec {
+    pub fn new(naming: &Naming, name: &str, help: &str, labels: &[&str], retention: Duration) -> TimestampedGaugeVec {
+        let opts = naming.opts(name, help);
+        let labels: Vec<String> = labels.iter().map(|label| label.to_string()).collect();
+        let desc = Desc::new(opts.fq_name(), opts.help.clone(), labels.clone(), opts.const_labels.clone()).expect("invalid metric name");
+        TimestampedGaugeVec {
+            desc,
+            opts,
+            labels,
+            retention,
+            samples: Default::default(),
+        }
+    }
+
+    pub fn register(naming: &Naming, name: &str, help: &str, labels: &[&str], retention: Duration) -> TimestampedGaugeVec {
+        let vec = TimestampedGaugeVec::new(naming, name, help, labels, retention);
+        prometheus::register(Box::new(vec.clone())).unwrap_or_else(|e| panic!("can not register gauge {}: {}", name, e));
+        vec
+    }
+
+    // `at` is unix seconds
+    pub fn set_at(&self, labels: &[&str], at: i64, value: f64) {
+        let labels = labels.iter().map(|label| label.to_string()).collect();
+        self.samples.lock().expect("failed to acuire lock").insert((labels, at), Sample {
+            value,
+            updated: Instant::now(),
+        });
+    }
+
+    pub fn reset(&self) {
+        self.samples.lock().expect("failed to acuire lock").clear();
+    }
+
+    fn collect_at(&self, now: Instant) -> Vec<MetricFamily> {
+        let mut samples = self.samples.lock().expect("failed to acuire lock");
+        samples.retain(|_, sample| now.saturating_duration_since(sample.updated) <= self.retention);
+
+        let labels: Vec<&str> = self.labels.iter().map(String::as_str).collect();
+        let mut family: Option<MetricFamily> = None;
+        for ((values, at), sample) in samples.iter() {
+            let vec = match GaugeVec::new(self.opts.clone(), &labels) {
+                Ok(vec) => vec,
+                Err(_) => continue,
+            };
+            let values: Vec<&str> = values.iter().map(String::as_str).collect();
+            vec.with_label_values(&values).set(sample.value);
+            for mut mf in vec.collect() {
+                let mut metrics = mf.take_metric();
+                for metric in metrics.iter_mut() {
+                    metric.set_timestamp_ms(at * 1000);
+                }
+                match &mut family {
+                    Some(family) => {
+                        for metric in metrics {
+                            family.mut_metric().push(metric);
+                        }
+                    },
+                    None => {
+                        mf.set_metric(metrics);
+                        family = Some(mf);
+                    },
+                }
+            }
+        }
+        family.into_iter().collect()
+    }
+}
+
+impl Collector for TimestampedGaugeVec {
+    fn desc(&self) -> Vec<&Desc> {
+        vec![&self.desc]
+    }
+
+    fn collect(&self) -> Vec<MetricFamily> {
+        self.collect_at(Instant::now())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prometheus_exporter::prometheus::proto::MetricType;
+
+    #[test]
+    fn test_collect() {
+        let cache = ValueCache::default();
+        let power = cache.gauge("instantaneous_energy", "Current Power Consumption in Watt");
+        let energy = cache.gauge_vec("cumulative_energy", "Cumulative Energy in kWh", &["direction"]);
+
+        // nothing is exposed before the first read
+        assert!(cache.collect().iter().all(|mf| mf.get_metric().is_empty()));
+
+        power.set(300.0);
+        energy.with_label_values(&["normal"]).set(1234.5);
+        energy.with_label_values(&["normal"]).set(1234.6);
+        let mfs = cache.collect();
+        assert_eq!(mfs.len(), 2);
+        assert_eq!(mfs[0].get_name(), "cumulative_energy");
+        assert_eq!(mfs[0].get_metric().len(), 1);
+        assert_eq!(mfs[0].get_metric()[0].get_label()[0].get_value(), "normal");
+        assert_eq!(mfs[0].get_metric()[0].get_gauge().get_value(), 1234.6);
+        assert_eq!(mfs[1].get_name(), "instantaneous_energy");
+        assert_eq!(mfs[1].get_metric()[0].get_gauge().get_value(), 300.0);
+
+        energy.reset();
+        assert!(cache.collect()[0].get_metric().is_empty());
+    }
+
+    #[test]
+    fn test_counter() {
+        let naming = Naming {
+            namespace: "smartmeter".to_string(),
+            ..Naming::default()
+        };
+        let cache = ValueCache::new(None, naming);
+        cache.counter_vec("energy_kwh_total", "Cumulative Energy in kWh", &["direction"])
+            .with_label_values(&["normal"]).set(1234.5);
+        let mfs = cache.collect();
+        assert_eq!(mfs[0].get_name(), "smartmeter_energy_kwh_total");
+        assert_eq!(mfs[0].get_field_type(), MetricType::COUNTER);
+        assert_eq!(mfs[0].get_metric()[0].get_counter().get_value(), 1234.5);
+    }
+
+    #[test]
+    fn test_staleness() {
+        for stale_value in [StaleValue::Omit, StaleValue::Nan] {
+            let cache = ValueCache::new(Some(Staleness { intervals: 3, value: stale_value }), Naming::default());
+            let power = cache.gauge("instantaneous_energy", "Current Power Consumption in Watt");
+            let serial = cache.gauge("serial", "never stale");
+            power.refreshed_every(Duration::from_secs(10));
+            let now = Instant::now();
+            power.set(300.0);
+            serial.set(1.0);
+
+            assert_eq!(cache.collect_at(now + Duration::from_secs(30))[0].get_metric()[0].get_gauge().get_value(), 300.0);
+
+            let mfs = cache.collect_at(now + Duration::from_secs(31));
+            match stale_value {
+                StaleValue::Omit => assert!(mfs[0].get_metric().is_empty()),
+                StaleValue::Nan => assert!(mfs[0].get_metric()[0].get_gauge().get_value().is_nan()),
+            }
+            assert_eq!(mfs[1].get_metric()[0].get_gauge().get_value(), 1.0);
+
+            // stale as soon as it is set, it was read long ago
+            let now = Instant::now();
+            power.set_as_of(300.0, now - Duration::from_secs(31));
+            let mfs = cache.collect_at(now);
+            match stale_value {
+                StaleValue::Omit => assert!(mfs[0].get_metric().is_empty()),
+                StaleValue::Nan => assert!(mfs[0].get_metric()[0].get_gauge().get_value().is_nan()),
+            }
+        }
+    }
+
+    #[test]
+    fn test_timestamped() {
+        let readings = TimestampedGaugeVec::new(&Naming::default(), "fixed_time_energy_slot_kwh", "Cumulative Energy", &["direction"], Duration::from_secs(1800));
+        let now = Instant::now();
+        readings.set_at(&["normal"], 1681486200, 1.5);
+        readings.set_at(&["reverse"], 1681486200, 0.5);
+        readings.set_at(&["normal"], 1681484400, 1.0);
+
+        let mfs = readings.collect_at(now);
+        assert_eq!(mfs.len(), 1);
+        let metrics = mfs[0].get_metric();
+        assert_eq!(metrics.len(), 3);
+        // each series together and in ascending order, so that the scrape appends them in order
+        assert_eq!(metrics[0].get_label()[0].get_value(), "normal");
+        assert_eq!(metrics[0].get_timestamp_ms(), 1681484400 * 1000);
+        assert_eq!(metrics[0].get_gauge().get_value(), 1.0);
+        assert_eq!(metrics[1].get_label()[0].get_value(), "normal");
+        assert_eq!(metrics[1].get_timestamp_ms(), 1681486200 * 1000);
+        assert_eq!(metrics[2].get_label()[0].get_value(), "reverse");
+
+        assert!(readings.collect_at(now + Duration::from_secs(1801)).is_empty());
+    }
+}
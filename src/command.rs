@@ -1,6 +1,12 @@
+use std::error::Error;
+use std::fmt;
+
 use bytes::{Bytes, BytesMut, BufMut};
 
-use crate::echonet_lite::{EchonetLite, EHd, EHD1_ECHONET_LITE, EHD2_FORMAT1, EData, EDataFormat1, EOJ_MANAGEMENT_CONTROLLER, EOJ_HOUSING_LOW_VOLTAGE_SMART_METER, Esv, EDataProperty, EpcLowVoltageSmartMeter};
+use crate::dialect::Dialect;
+use crate::echonet_lite::EchonetLite;
+use crate::parser::{Response, FailCode};
+use crate::register::Register;
 
 pub type Addr64 = str;
 pub type IpAddr = str;
@@ -16,9 +22,12 @@ pub enum Command<'a> {
     ActiveScan {
         duration: u8,
     },
+    // energy detection on every channel
+    EdScan {
+        duration: u8,
+    },
     SkSreg {
-        sreg: u8,
-        val: u32,
+        register: Register,
     },
     SkLl64 {
         addr64: &'a Addr64,
@@ -26,13 +35,103 @@ pub enum Command<'a> {
     SkJoin {
         ipaddr: &'a IpAddr,
     },
-    SendEnergyRequest {
+    // information about the module
+    SkVer,
+    SkAppVer,
+    SkInfo,
+    SkTable {
+        mode: u8,
+    },
+    SkSregRead {
+        sreg: u8,
+    },
+    // read and write the option register (encoding of the ERXUDP payload)
+    ROpt,
+    WOpt {
+        mode: u8,
+    },
+    SendEchonetLite {
         ipaddr: &'a IpAddr,
+        frame: EchonetLite,
+    },
+}
+
+// the module answered a command with FAIL ERxx
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct CommandError {
+    pub command: Option<String>,
+    pub code: FailCode,
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} failed: {}", self.command.as_deref().unwrap_or("command"), self.code)
     }
 }
 
-impl Into<Bytes> for Command<'_> {
-    fn into(self) -> Bytes {
+impl Error for CommandError {}
+
+// turn FAIL into an error of the command that caused it
+pub fn check_response(r: Response) -> Result<Response, CommandError> {
+    match r {
+        Response::Fail { command, code } => Err(CommandError { command, code }),
+        r => Ok(r),
+    }
+}
+
+// side of BP35C0: 0 is the B route
+const SIDE_B_ROUTE: u8 = 0;
+
+fn sksendto(ipaddr: &IpAddr, frame: EchonetLite, dialect: Dialect) -> Bytes {
+    let frame: Bytes = frame.into();
+
+    let mut cmd = BytesMut::from(format!("SKSENDTO 1 {} 0E1A 1 ", ipaddr).as_bytes());
+    if dialect == Dialect::Bp35c0 {
+        cmd.put(format!("{} ", SIDE_B_ROUTE).as_bytes());
+    }
+    cmd.put(format!("{:>04X} ", frame.len()).as_bytes());
+    cmd.put(frame);
+    cmd.put(&b"\r\n"[..]);
+    cmd.into()
+}
+
+// modes of SKSCAN
+const SCAN_MODE_ED: u8 = 0;
+const SCAN_MODE_ACTIVE: u8 = 2;
+
+// scan all the channels
+fn skscan(mode: u8, duration: u8, dialect: Dialect) -> Bytes {
+    let mut cmd = BytesMut::from(format!("SKSCAN {} FFFFFFFF {:X}", mode, duration).as_bytes());
+    if dialect == Dialect::Bp35c0 {
+        cmd.put(format!(" {}", SIDE_B_ROUTE).as_bytes());
+    }
+    cmd.put(&b"\r\n"[..]);
+    cmd.into()
+}
+
+impl Command<'_> {
+    // as the module echoes it back, e.g. "SKSREG"
+    pub fn name(&self) -> &'static str {
+        match self {
+            Command::SkReset => "SKRESET",
+            Command::SkSetRbid { .. } => "SKSETRBID",
+            Command::SkSetPwd { .. } => "SKSETPWD",
+            Command::ActiveScan { .. } | Command::EdScan { .. } => "SKSCAN",
+            Command::SkSreg { .. } | Command::SkSregRead { .. } => "SKSREG",
+            Command::SkLl64 { .. } => "SKLL64",
+            Command::SkJoin { .. } => "SKJOIN",
+            Command::SkVer => "SKVER",
+            Command::SkAppVer => "SKAPPVER",
+            Command::SkInfo => "SKINFO",
+            Command::SkTable { .. } => "SKTABLE",
+            Command::ROpt => "ROPT",
+            Command::WOpt { .. } => "WOPT",
+            Command::SendEchonetLite { .. } => "SKSENDTO",
+        }
+    }
+
+    // the command line in the format of `dialect`
+    pub fn encode(self, dialect: Dialect) -> Bytes {
         match self {
             Command::SkReset => {
                 Bytes::from_static(b"SKRESET\r\n")
@@ -54,18 +153,17 @@ impl Into<Bytes> for Command<'_> {
                 cmd.into()
             },
             Command::ActiveScan { duration } => {
-                let mut cmd = BytesMut::new();
-                cmd.put(&b"SKSCAN 2 FFFFFFFF "[..]);
-                cmd.put(format!("{:X}", duration).as_bytes());
-                cmd.put(&b"\r\n"[..]);
-                cmd.into()
+                skscan(SCAN_MODE_ACTIVE, duration, dialect)
+            },
+            Command::EdScan { duration } => {
+                skscan(SCAN_MODE_ED, duration, dialect)
             },
-            Command::SkSreg { sreg, val } => {
+            Command::SkSreg { register } => {
                 let mut cmd = BytesMut::new();
                 cmd.put(&b"SKSREG S"[..]);
-                cmd.put(format!("{:X}", sreg).as_bytes());
+                cmd.put(format!("{:X}", register.sreg()).as_bytes());
                 cmd.put(&b" "[..]);
-                cmd.put(format!("{:X}", val).as_bytes());
+                cmd.put(register.format_val().as_bytes());
                 cmd.put(&b"\r\n"[..]);
                 cmd.into()
             },
@@ -83,32 +181,29 @@ impl Into<Bytes> for Command<'_> {
                 cmd.put(&b"\r\n"[..]);
                 cmd.into()
             },
-            Command::SendEnergyRequest { ipaddr } => {
-                // get current power consumption
-                let get_now_p = EchonetLite {
-                    ehd: EHd {
-                        ehd1: EHD1_ECHONET_LITE,
-                        ehd2: EHD2_FORMAT1,
-                        tid: 0x0001,
-                    },
-                    edata: EData::EDataFormat1(EDataFormat1 {
-                        seoj: EOJ_MANAGEMENT_CONTROLLER,
-                        deoj: EOJ_HOUSING_LOW_VOLTAGE_SMART_METER,
-                        esv: Esv::PROP_READ,
-                        opc: 0x01,
-                        props: vec![EDataProperty {
-                            epc: EpcLowVoltageSmartMeter::INSTANTANEOUS_ENERGY,
-                            pdc: 0x00,
-                            edt: Bytes::new(),
-                        }],
-                    })
-                };
-                let get_now_p: Bytes = get_now_p.into();
-
-                let mut cmd = BytesMut::from(format!("SKSENDTO 1 {} 0E1A 1 {:>04X} ", ipaddr, get_now_p.len()).as_bytes());
-                cmd.put(get_now_p);
-                cmd.put(&b"\r\n"[..]);
-                cmd.into()
+            Command::SkVer => {
+                Bytes::from_static(b"SKVER\r\n")
+            },
+            Command::SkAppVer => {
+                Bytes::from_static(b"SKAPPVER\r\n")
+            },
+            Command::SkInfo => {
+                Bytes::from_static(b"SKINFO\r\n")
+            },
+            Command::SkTable { mode } => {
+                Bytes::from(format!("SKTABLE {:X}\r\n", mode))
+            },
+            Command::SkSregRead { sreg } => {
+                Bytes::from(format!("SKSREG S{:X}\r\n", sreg))
+            },
+            Command::ROpt => {
+                Bytes::from_static(b"ROPT\r\n")
+            },
+            Command::WOpt { mode } => {
+                Bytes::from(format!("WOPT {:02X}\r\n", mode))
+            },
+            Command::SendEchonetLite { ipaddr, frame } => {
+                sksendto(ipaddr, frame, dialect)
             },
         } 
     }
@@ -118,52 +213,109 @@ impl Into<Bytes> for Command<'_> {
 mod tests {
 
     use super::*;
+    use crate::echonet_lite::{EHd, EHD1_ECHONET_LITE, EHD2_FORMAT1, EData, EDataFormat1, EOJ_MANAGEMENT_CONTROLLER, EOJ_HOUSING_LOW_VOLTAGE_SMART_METER, Esv};
+    use crate::value::MeterValue;
 
     #[test]
     fn test_sk_reset() {
         let cmd = Command::SkReset;
-        assert_eq!(std::convert::Into::<Bytes>::into(cmd), Bytes::from_static(b"SKRESET\r\n"));
+        assert_eq!(cmd.encode(Dialect::Bp35a1), Bytes::from_static(b"SKRESET\r\n"));
     }
 
     #[test]
     fn test_sk_set_rbid() {
         let cmd = Command::SkSetRbid { id: "12345678" };
-        assert_eq!(std::convert::Into::<Bytes>::into(cmd), Bytes::from_static(b"SKSETRBID 12345678\r\n"));
+        assert_eq!(cmd.encode(Dialect::Bp35a1), Bytes::from_static(b"SKSETRBID 12345678\r\n"));
     }
 
     #[test]
     fn test_sk_set_pwd() {
         let cmd = Command::SkSetPwd { pwd: "123XXXXXXXXX" };
-        assert_eq!(std::convert::Into::<Bytes>::into(cmd), Bytes::from_static(b"SKSETPWD C 123XXXXXXXXX\r\n"));
+        assert_eq!(cmd.encode(Dialect::Bp35a1), Bytes::from_static(b"SKSETPWD C 123XXXXXXXXX\r\n"));
     }
 
     #[test]
     fn test_active_scan() {
         let cmd = Command::ActiveScan { duration: 6 };
-        assert_eq!(std::convert::Into::<Bytes>::into(cmd), Bytes::from_static(b"SKSCAN 2 FFFFFFFF 6\r\n"));
+        assert_eq!(cmd.encode(Dialect::Bp35a1), Bytes::from_static(b"SKSCAN 2 FFFFFFFF 6\r\n"));
+    }
+
+    #[test]
+    fn test_ed_scan() {
+        let cmd = Command::EdScan { duration: 4 };
+        assert_eq!(cmd.encode(Dialect::Bp35a1), Bytes::from_static(b"SKSCAN 0 FFFFFFFF 4\r\n"));
     }
 
     #[test]
     fn test_sk_sreg() {
-        let cmd = Command::SkSreg { sreg: 0x02, val: 0x21 };
-        assert_eq!(std::convert::Into::<Bytes>::into(cmd), Bytes::from_static(b"SKSREG S2 21\r\n"));
+        let cmd = Command::SkSreg { register: Register::Channel(0x21) };
+        assert_eq!(cmd.encode(Dialect::Bp35a1), Bytes::from_static(b"SKSREG S2 21\r\n"));
+
+        let cmd = Command::SkSreg { register: Register::PairingId(0x0011AABB) };
+        assert_eq!(cmd.encode(Dialect::Bp35a1), Bytes::from_static(b"SKSREG SA 0011AABB\r\n"));
     }
 
     #[test]
     fn test_sk_ll64() {
         let cmd = Command::SkLl64 { addr64: "0123456789ABCDEF" };
-        assert_eq!(std::convert::Into::<Bytes>::into(cmd), Bytes::from_static(b"SKLL64 0123456789ABCDEF\r\n"));
+        assert_eq!(cmd.encode(Dialect::Bp35a1), Bytes::from_static(b"SKLL64 0123456789ABCDEF\r\n"));
     }
 
     #[test]
     fn test_sk_join() {
         let cmd = Command::SkJoin { ipaddr: "FE80:0000:0000:0000:0123:4567:89ab:cdef" };
-        assert_eq!(std::convert::Into::<Bytes>::into(cmd), Bytes::from_static(b"SKJOIN FE80:0000:0000:0000:0123:4567:89ab:cdef\r\n"));
+        assert_eq!(cmd.encode(Dialect::Bp35a1), Bytes::from_static(b"SKJOIN FE80:0000:0000:0000:0123:4567:89ab:cdef\r\n"));
     }
 
     #[test]
-    fn test_send_energy_request() {
-        let cmd = Command::SendEnergyRequest { ipaddr: "FE80:0000:0000:0000:0123:4567:89ab:cdef" };
-        assert_eq!(std::convert::Into::<Bytes>::into(cmd), Bytes::from_static(b"SKSENDTO 1 FE80:0000:0000:0000:0123:4567:89ab:cdef 0E1A 1 000E \x10\x81\x00\x01\x05\xFF\x01\x02\x88\x01\x62\x01\xE7\x00\r\n"));
+    fn test_module_info() {
+        assert_eq!(Command::SkVer.encode(Dialect::Bp35a1), Bytes::from_static(b"SKVER\r\n"));
+        assert_eq!(Command::SkAppVer.encode(Dialect::Bp35a1), Bytes::from_static(b"SKAPPVER\r\n"));
+        assert_eq!(Command::SkInfo.encode(Dialect::Bp35a1), Bytes::from_static(b"SKINFO\r\n"));
+        assert_eq!(Command::SkTable { mode: 2 }.encode(Dialect::Bp35a1), Bytes::from_static(b"SKTABLE 2\r\n"));
+        assert_eq!(Command::SkSregRead { sreg: 0xFE }.encode(Dialect::Bp35a1), Bytes::from_static(b"SKSREG SFE\r\n"));
+    }
+
+    #[test]
+    fn test_opt() {
+        assert_eq!(Command::ROpt.encode(Dialect::Bp35a1), Bytes::from_static(b"ROPT\r\n"));
+        assert_eq!(Command::WOpt { mode: 0x01 }.encode(Dialect::Bp35a1), Bytes::from_static(b"WOPT 01\r\n"));
+    }
+
+    #[test]
+    fn test_bp35c0() {
+        let cmd = Command::ActiveScan { duration: 6 };
+        assert_eq!(cmd.encode(Dialect::Bp35c0), Bytes::from_static(b"SKSCAN 2 FFFFFFFF 6 0\r\n"));
+    }
+
+    #[test]
+    fn test_check_response() {
+        assert_eq!(check_response(Response::Ok), Ok(Response::Ok));
+
+        let e = check_response(Response::Fail { command: Some("SKSREG".to_string()), code: FailCode::ParameterOutOfRange }).unwrap_err();
+        assert_eq!(e.code, FailCode::ParameterOutOfRange);
+        assert_eq!(e.to_string(), "SKSREG failed: ER06 (parameter out of range)");
+    }
+
+    #[test]
+    fn test_send_echonet_lite() {
+        let cmd = Command::SendEchonetLite {
+            ipaddr: "FE80:0000:0000:0000:0123:4567:89ab:cdef",
+            frame: EchonetLite {
+                ehd: EHd {
+                    ehd1: EHD1_ECHONET_LITE,
+                    ehd2: EHD2_FORMAT1,
+                    tid: 0x0002,
+                },
+                edata: EData::EDataFormat1(EDataFormat1 {
+                    seoj: EOJ_MANAGEMENT_CONTROLLER,
+                    deoj: EOJ_HOUSING_LOW_VOLTAGE_SMART_METER,
+                    esv: Esv::SetC,
+                    opc: 0x01,
+                    props: vec![MeterValue::DayForHistoricalData(1).encode().unwrap()],
+                })
+            },
+        };
+        assert_eq!(cmd.encode(Dialect::Bp35a1), Bytes::from_static(b"SKSENDTO 1 FE80:0000:0000:0000:0123:4567:89ab:cdef 0E1A 1 000F \x10\x81\x00\x02\x05\xFF\x01\x02\x88\x01\x61\x01\xE5\x01\x01\r\n"));
     }
 }
\ No newline at end of file
@@ -0,0 +1,166 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use crate::cache::{StaleValue, Staleness};
+use crate::dialect::Dialect;
+use crate::metrics::Naming;
+
+// settings read from the environment at startup
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Config {
+    // SMARTMETER_ECHO_BACK: whether the module echoes commands back (SFE register)
+    pub echo_back: bool,
+    // SMARTMETER_ASCII_PAYLOAD: whether ERXUDP carries the payload as hex digits (WOPT)
+    pub ascii_payload: bool,
+    // SMARTMETER_DIALECT: formats of the module (bp35a1, bp35c0), detected from SKVER if not set
+    pub dialect: Option<Dialect>,
+    // SMARTMETER_ED_SCAN: survey the noise of every channel before joining
+    pub ed_scan: bool,
+    // SMARTMETER_READ_ON_SCRAPE: seconds. A scrape triggers a read unless the last one is this recent
+    pub read_on_scrape: Option<Duration>,
+    // SMARTMETER_STALE_INTERVALS (0 disables) and SMARTMETER_STALE_VALUE (omit, nan):
+    // what a scrape gets for a value which has not been refreshed for that many intervals
+    pub staleness: Option<Staleness>,
+    // SMARTMETER_NAMESPACE and SMARTMETER_CONST_LABELS (e.g. "site=home,meter=1")
+    pub naming: Naming,
+    // SMARTMETER_LEGACY_METRICS: also publish the names before the namespace was introduced
+    pub legacy_metrics: bool,
+    // SMARTMETER_STATUS_ADDR: e.g. "0.0.0.0:9187", where the status of the smartmeter is served as JSON
+    pub status_addr: Option<SocketAddr>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            // halves the UART traffic
+            echo_back: false,
+            ascii_payload: false,
+            dialect: None,
+            ed_scan: false,
+            // scrapes are answered from the cache
+            read_on_scrape: None,
+            staleness: Some(Staleness {
+                intervals: 3,
+                value: StaleValue::Omit,
+            }),
+            naming: Naming {
+                namespace: "smartmeter".to_string(),
+                const_labels: BTreeMap::new(),
+            },
+            legacy_metrics: false,
+            status_addr: None,
+        }
+    }
+}
+
+fn parse_bool(name: &str, value: &str) -> Result<bool, Box<dyn Error>> {
+    match value.to_ascii_lowercase().as_str() {
+        "1" | "on" | "true" | "yes" => Ok(true),
+        "0" | "off" | "false" | "no" => Ok(false),
+        _ => Err(format!("invalid value of {}: {}", name, value).into()),
+    }
+}
+
+fn parse_secs(name: &str, value: &str) -> Result<Duration, Box<dyn Error>> {
+    value.parse().map(Duration::from_secs).map_err(|_| format!("invalid value of {}: {}", name, value).into())
+}
+
+// "name=value,name=value"
+fn parse_labels(name: &str, value: &str) -> Result<BTreeMap<String, String>, Box<dyn Error>> {
+    value.split(',')
+        .filter(|pair| !pair.trim().is_empty())
+        .map(|pair| match pair.split_once('=') {
+            Some((label, label_value)) if !label.trim().is_empty() => Ok((label.trim().to_string(), label_value.trim().to_string())),
+            _ => Err(format!("invalid value of {}: {}", name, value).into()),
+        })
+        .collect()
+}
+
+impl Config {
+    pub fn from_env() -> Result<Config, Box<dyn Error>> {
+        Config::from_vars(|name| std::env::var(name).ok())
+    }
+
+    fn from_vars(var: impl Fn(&str) -> Option<String>) -> Result<Config, Box<dyn Error>> {
+        let mut config = Config::default();
+        if let Some(value) = var("SMARTMETER_ECHO_BACK") {
+            config.echo_back = parse_bool("SMARTMETER_ECHO_BACK", &value)?;
+        }
+        if let Some(value) = var("SMARTMETER_ASCII_PAYLOAD") {
+            config.ascii_payload = parse_bool("SMARTMETER_ASCII_PAYLOAD", &value)?;
+        }
+        if let Some(value) = var("SMARTMETER_DIALECT") {
+            config.dialect = Some(value.parse()?);
+        }
+        if let Some(value) = var("SMARTMETER_ED_SCAN") {
+            config.ed_scan = parse_bool("SMARTMETER_ED_SCAN", &value)?;
+        }
+        if let Some(value) = var("SMARTMETER_READ_ON_SCRAPE") {
+            config.read_on_scrape = Some(parse_secs("SMARTMETER_READ_ON_SCRAPE", &value)?);
+        }
+        if let Some(value) = var("SMARTMETER_STALE_INTERVALS") {
+            let intervals = value.parse().map_err(|_| format!("invalid value of SMARTMETER_STALE_INTERVALS: {}", value))?;
+            config.staleness = (intervals > 0).then_some(Staleness { intervals, value: StaleValue::Omit });
+        }
+        if let Some(value) = var("SMARTMETER_STALE_VALUE") {
+            let value = value.parse()?;
+            config.staleness = config.staleness.map(|staleness| Staleness { value, ..staleness });
+        }
+        if let Some(value) = var("SMARTMETER_NAMESPACE") {
+            config.naming.namespace = value;
+        }
+        if let Some(value) = var("SMARTMETER_CONST_LABELS") {
+            config.naming.const_labels = parse_labels("SMARTMETER_CONST_LABELS", &value)?;
+        }
+        if let Some(value) = var("SMARTMETER_LEGACY_METRICS") {
+            config.legacy_metrics = parse_bool("SMARTMETER_LEGACY_METRICS", &value)?;
+        }
+        if let Some(value) = var("SMARTMETER_STATUS_ADDR") {
+            config.status_addr = Some(value.parse().map_err(|_| format!("invalid value of SMARTMETER_STATUS_ADDR: {}", value))?);
+        }
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_vars() {
+        assert_eq!(Config::from_vars(|_| None).unwrap(), Config::default());
+
+        let config = Config::from_vars(|name| (name == "SMARTMETER_ECHO_BACK").then(|| "On".to_string())).unwrap();
+        assert!(config.echo_back);
+        assert!(!config.ascii_payload);
+
+        let config = Config::from_vars(|name| (name == "SMARTMETER_DIALECT").then(|| "BP35C2".to_string())).unwrap();
+        assert_eq!(config.dialect, Some(Dialect::Bp35c0));
+
+        let config = Config::from_vars(|name| (name == "SMARTMETER_READ_ON_SCRAPE").then(|| "30".to_string())).unwrap();
+        assert_eq!(config.read_on_scrape, Some(Duration::from_secs(30)));
+
+        let config = Config::from_vars(|name| match name {
+            "SMARTMETER_STALE_INTERVALS" => Some("5".to_string()),
+            "SMARTMETER_STALE_VALUE" => Some("NaN".to_string()),
+            _ => None,
+        }).unwrap();
+        assert_eq!(config.staleness, Some(Staleness { intervals: 5, value: StaleValue::Nan }));
+        let config = Config::from_vars(|name| (name == "SMARTMETER_STALE_INTERVALS").then(|| "0".to_string())).unwrap();
+        assert_eq!(config.staleness, None);
+
+        let config = Config::from_vars(|name| (name == "SMARTMETER_CONST_LABELS").then(|| "site=home, meter=1".to_string())).unwrap();
+        assert_eq!(config.naming.const_labels, BTreeMap::from([
+            ("meter".to_string(), "1".to_string()),
+            ("site".to_string(), "home".to_string()),
+        ]));
+        assert!(Config::from_vars(|name| (name == "SMARTMETER_CONST_LABELS").then(|| "site".to_string())).is_err());
+
+        let config = Config::from_vars(|name| (name == "SMARTMETER_STATUS_ADDR").then(|| "0.0.0.0:9187".to_string())).unwrap();
+        assert_eq!(config.status_addr, Some("0.0.0.0:9187".parse().unwrap()));
+
+        assert!(Config::from_vars(|_| Some("maybe".to_string())).is_err());
+    }
+}
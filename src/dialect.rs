@@ -0,0 +1,61 @@
+use std::error::Error;
+use std::str::FromStr;
+
+// command and response formats of a SKSTACK IP firmware generation
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum Dialect {
+    // BP35A1 and others with SKSTACK IP 1.2.x
+    #[default]
+    Bp35a1,
+    // BP35C0 / BP35C2: RSSI in ERXUDP and EPANDESC, side (B route or HAN) in SKSCAN, SKSENDTO and ERXUDP
+    Bp35c0,
+}
+
+// SKSTACK IP 1.2.x is the BP35A1 generation, anything later has the extended formats
+const FIRST_BP35C0_VERSION: (u32, u32) = (1, 3);
+
+impl Dialect {
+    // from the version SKVER reports, e.g. "1.2.10"
+    pub fn from_version(version: &str) -> Option<Dialect> {
+        let mut numbers = version.trim().split('.').map(|n| n.parse::<u32>());
+        let major = numbers.next()?.ok()?;
+        let minor = numbers.next()?.ok()?;
+        if (major, minor) < FIRST_BP35C0_VERSION {
+            Some(Dialect::Bp35a1)
+        } else {
+            Some(Dialect::Bp35c0)
+        }
+    }
+}
+
+impl FromStr for Dialect {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "bp35a1" => Ok(Dialect::Bp35a1),
+            "bp35c0" | "bp35c2" => Ok(Dialect::Bp35c0),
+            _ => Err(format!("unknown dialect: {}", s).into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_version() {
+        assert_eq!(Dialect::from_version("1.2.10"), Some(Dialect::Bp35a1));
+        assert_eq!(Dialect::from_version("1.5.2"), Some(Dialect::Bp35c0));
+        assert_eq!(Dialect::from_version("2.0"), Some(Dialect::Bp35c0));
+        assert_eq!(Dialect::from_version("rev26e"), None);
+    }
+
+    #[test]
+    fn test_from_str() {
+        assert_eq!("BP35A1".parse::<Dialect>().unwrap(), Dialect::Bp35a1);
+        assert_eq!("bp35c2".parse::<Dialect>().unwrap(), Dialect::Bp35c0);
+        assert!("bp35x".parse::<Dialect>().is_err());
+    }
+}
@@ -1,14 +1,15 @@
 use std::fmt;
+use std::collections::BTreeSet;
 use bytes::{Bytes, BytesMut, BufMut};
 
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub struct EchonetLite {
     pub ehd: EHd,
     pub edata: EData,
 }
 
-#[derive(PartialEq, Default, Clone, Copy)]
+#[derive(PartialEq, Eq, Default, Clone, Copy)]
 pub struct EHd {
     pub ehd1: u8,
     pub ehd2: u8,
@@ -17,8 +18,9 @@ pub struct EHd {
 
 pub const EHD1_ECHONET_LITE: u8 = 0x10;
 pub const EHD2_FORMAT1: u8 = 0x81;
+pub const EHD2_FORMAT2: u8 = 0x82;
 
-#[derive(PartialEq, Default, Clone)]
+#[derive(PartialEq, Eq, Default, Clone)]
 pub struct EDataProperty {
     pub epc: u8,
     pub pdc: u8,
@@ -37,51 +39,273 @@ pub const EOJ_HOUSING_LOW_VOLTAGE_SMART_METER: Eoj = Eoj {
     class_code: 0x88,
     instance_code: 0x01,
 };
+pub const EOJ_HOUSING_HIGH_VOLTAGE_SMART_METER: Eoj = Eoj {
+    class_group_code: 0x02,
+    class_code: 0x8A,
+    instance_code: 0x01,
+};
 pub const EOJ_MANAGEMENT_CONTROLLER: Eoj = Eoj {
     class_group_code: 0x05,
     class_code: 0xFF,
     instance_code: 0x01,
 };
+pub const EOJ_NODE_PROFILE: Eoj = Eoj {
+    class_group_code: 0x0E,
+    class_code: 0xF0,
+    instance_code: 0x01,
+};
+
+// properties shared by every device object
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[non_exhaustive]
+pub struct EpcSuperClass;
+impl EpcSuperClass {
+    pub const OPERATION_STATUS: u8 = 0x80;
+    pub const VERSION_INFORMATION: u8 = 0x82;
+    pub const MANUFACTURER_CODE: u8 = 0x8A;
+    pub const PRODUCT_CODE: u8 = 0x8C;
+    pub const PRODUCTION_NUMBER: u8 = 0x8D;
+    pub const STATUS_CHANGE_ANNOUNCEMENT_PROPERTY_MAP: u8 = 0x9D;
+    pub const SET_PROPERTY_MAP: u8 = 0x9E;
+    pub const GET_PROPERTY_MAP: u8 = 0x9F;
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[non_exhaustive]
+pub struct EpcNodeProfile;
+impl EpcNodeProfile {
+    pub const INSTANCE_LIST_NOTIFICATION: u8 = 0xD5;
+    pub const SELF_NODE_INSTANCE_LIST_S: u8 = 0xD6;
+}
 
 #[derive(Debug, PartialEq, Clone, Copy)]
 #[non_exhaustive]
 pub struct EpcLowVoltageSmartMeter;
 impl EpcLowVoltageSmartMeter {
     pub const STATUS: u8 = 0x80;
+    pub const COEFFICIENT: u8 = 0xD3;
     pub const EFFECTIVE_DIGITS_OF_CUMULATIVE_ENERGY: u8 = 0xD7;
     pub const CUMULATIVE_ENERGY_NORMAL_DIRECTION: u8 = 0xE0;
     pub const CUMULATIVE_ENERGY_REVERSE_DIRECTION: u8 = 0xE3;
     pub const CUMULATIVE_ENERGY_UNIT: u8 = 0xE1;
+    pub const HISTORICAL_CUMULATIVE_ENERGY_1_NORMAL_DIRECTION: u8 = 0xE2;
+    pub const HISTORICAL_CUMULATIVE_ENERGY_1_REVERSE_DIRECTION: u8 = 0xE4;
+    pub const DAY_FOR_HISTORICAL_DATA_1: u8 = 0xE5;
     pub const INSTANTANEOUS_ENERGY: u8 = 0xE7;
     pub const INSTANTANEOUS_CURRENT: u8 = 0xE8;
     pub const CUMULATIVE_ENERGY_FIXED_TIME_NORMAL_DIRECTION: u8 = 0xEA;
     pub const CUMULATIVE_ENERGY_FIXED_TIME_REVERSE_DIRECTION: u8 = 0xEB;
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[non_exhaustive]
+pub struct EpcHighVoltageSmartMeter;
+impl EpcHighVoltageSmartMeter {
+    pub const MONTHLY_MAXIMUM_DEMAND: u8 = 0xC1;
+    pub const CUMULATIVE_MAXIMUM_DEMAND: u8 = 0xC2;
+    pub const DEMAND_FIXED_TIME: u8 = 0xC3;
+    pub const EFFECTIVE_DIGITS_OF_DEMAND: u8 = 0xC4;
+    pub const DEMAND_UNIT: u8 = 0xC5;
+    pub const CUMULATIVE_REACTIVE_ENERGY_LAG_FIXED_TIME: u8 = 0xCA;
+    pub const COEFFICIENT: u8 = 0xD3;
+    pub const CUMULATIVE_ACTIVE_ENERGY: u8 = 0xE0;
+    pub const CUMULATIVE_ACTIVE_ENERGY_UNIT: u8 = 0xE1;
+    pub const CUMULATIVE_ACTIVE_ENERGY_FIXED_TIME: u8 = 0xE2;
+    pub const EFFECTIVE_DIGITS_OF_CUMULATIVE_ACTIVE_ENERGY: u8 = 0xE3;
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub enum EData {
     EDataFormat1(EDataFormat1),
-    InvalidEData(Bytes),
+    // arbitrary message format, passed through as is
+    EDataFormat2(Bytes),
 }
 
-#[derive(PartialEq, Default, Clone)]
+#[derive(PartialEq, Eq, Clone)]
 pub struct EDataFormat1 {
     pub seoj: Eoj, 
     pub deoj: Eoj, 
-    pub esv: u8,
+    pub esv: Esv,
     pub opc: u8,
     pub props: Vec<EDataProperty>,
 }
 
-#[derive(Debug, PartialEq, Clone, Copy)]
-#[non_exhaustive]
-pub struct Esv;
+// ECHONET Lite service
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Esv {
+    // requests
+    SetI,
+    SetC,
+    Get,
+    InfReq,
+    SetGet,
+    // responses and notifications
+    SetRes,
+    GetRes,
+    Inf,
+    Infc,
+    InfcRes,
+    SetGetRes,
+    // responses when the request could not be processed
+    SetISna,
+    SetCSna,
+    GetSna,
+    InfSna,
+    SetGetSna,
+    // kept as is, so that frames with a service we don't know are not lost
+    Unknown(u8),
+}
+
+impl From<u8> for Esv {
+    fn from(code: u8) -> Self {
+        match code {
+            0x60 => Esv::SetI,
+            0x61 => Esv::SetC,
+            0x62 => Esv::Get,
+            0x63 => Esv::InfReq,
+            0x6E => Esv::SetGet,
+            0x71 => Esv::SetRes,
+            0x72 => Esv::GetRes,
+            0x73 => Esv::Inf,
+            0x74 => Esv::Infc,
+            0x7A => Esv::InfcRes,
+            0x7E => Esv::SetGetRes,
+            0x50 => Esv::SetISna,
+            0x51 => Esv::SetCSna,
+            0x52 => Esv::GetSna,
+            0x53 => Esv::InfSna,
+            0x5E => Esv::SetGetSna,
+            code => Esv::Unknown(code),
+        }
+    }
+}
+
+impl From<Esv> for u8 {
+    fn from(esv: Esv) -> Self {
+        match esv {
+            Esv::SetI => 0x60,
+            Esv::SetC => 0x61,
+            Esv::Get => 0x62,
+            Esv::InfReq => 0x63,
+            Esv::SetGet => 0x6E,
+            Esv::SetRes => 0x71,
+            Esv::GetRes => 0x72,
+            Esv::Inf => 0x73,
+            Esv::Infc => 0x74,
+            Esv::InfcRes => 0x7A,
+            Esv::SetGetRes => 0x7E,
+            Esv::SetISna => 0x50,
+            Esv::SetCSna => 0x51,
+            Esv::GetSna => 0x52,
+            Esv::InfSna => 0x53,
+            Esv::SetGetSna => 0x5E,
+            Esv::Unknown(code) => code,
+        }
+    }
+}
+
+// classified by the code ranges of the specification, so that unknown services are classified as well
 impl Esv {
-    pub const PROP_WRITE_NO_RES: u8 = 0x60;
-    pub const PROP_WRITE: u8 = 0x61;
-    pub const PROP_READ: u8 = 0x62;
-    pub const PROP_NOTIFY: u8 = 0x62;
-    pub const PROP_WRITE_READ: u8 = 0x6E;
+    pub fn is_request(self) -> bool {
+        (0x60..=0x6F).contains(&u8::from(self))
+    }
+
+    pub fn is_response(self) -> bool {
+        (0x70..=0x7F).contains(&u8::from(self))
+    }
+
+    pub fn is_error(self) -> bool {
+        (0x50..=0x5F).contains(&u8::from(self))
+    }
+
+    // sent by a node on its own, not in reply to one of our requests
+    pub fn is_notification(self) -> bool {
+        matches!(self, Esv::Inf | Esv::Infc)
+    }
+}
+
+// EDT of 9D/9E/9F
+// The first byte is the number of properties. Up to 15 properties are listed as EPCs,
+// otherwise a 16 bytes bitmap follows, where bit `b` of byte `n` stands for EPC 0x80 + (b << 4) + n.
+#[derive(PartialEq, Eq, Default, Clone)]
+pub struct PropertyMap(BTreeSet<u8>);
+
+const PROPERTY_MAP_BITMAP_THRESHOLD: usize = 16;
+
+impl PropertyMap {
+    pub fn decode(edt: &[u8]) -> Option<PropertyMap> {
+        let (&count, rest) = edt.split_first()?;
+        let epcs: BTreeSet<u8> = if (count as usize) < PROPERTY_MAP_BITMAP_THRESHOLD {
+            if rest.len() != count as usize {
+                return None;
+            }
+            rest.iter().copied().collect()
+        } else {
+            if rest.len() != 16 {
+                return None;
+            }
+            rest.iter().enumerate()
+                .flat_map(|(n, byte)| (0..8).filter(move |b| byte & (1 << b) != 0).map(move |b| 0x80 + (b << 4) + n as u8))
+                .collect()
+        };
+
+        if epcs.len() != count as usize {
+            return None;
+        }
+        Some(PropertyMap(epcs))
+    }
+
+    pub fn contains(&self, epc: u8) -> bool {
+        self.0.contains(&epc)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = u8> + '_ {
+        self.0.iter().copied()
+    }
+}
+
+impl FromIterator<u8> for PropertyMap {
+    fn from_iter<T: IntoIterator<Item = u8>>(iter: T) -> Self {
+        PropertyMap(iter.into_iter().collect())
+    }
+}
+
+impl fmt::Debug for PropertyMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set()
+         .entries(self.0.iter().map(|epc| format!("{:#x}", epc)))
+         .finish()
+    }
+}
+
+impl Into<Bytes> for PropertyMap {
+    fn into(self) -> Bytes {
+        let mut bytes = BytesMut::new();
+        bytes.put_u8(self.0.len() as u8);
+        if self.0.len() < PROPERTY_MAP_BITMAP_THRESHOLD {
+            bytes.extend(self.0.iter());
+        } else {
+            let mut bitmap = [0u8; 16];
+            for epc in self.0.iter().filter(|&&epc| epc >= 0x80) {
+                bitmap[(epc & 0x0F) as usize] |= 1 << ((epc >> 4) - 8);
+            }
+            bytes.put(&bitmap[..]);
+        }
+        bytes.freeze()
+    }
+}
+
+// EDT of D5/D6: number of instances followed by their EOJs
+pub fn decode_instance_list(edt: &[u8]) -> Option<Vec<Eoj>> {
+    let (&count, rest) = edt.split_first()?;
+    if rest.len() != 3 * count as usize {
+        return None;
+    }
+    Some(rest.chunks(3).map(|eoj| Eoj {
+        class_group_code: eoj[0],
+        class_code: eoj[1],
+        instance_code: eoj[2],
+    }).collect())
 }
 
 impl fmt::Debug for EHd {
@@ -94,6 +318,13 @@ impl fmt::Debug for EHd {
     }
 }
 
+impl Eoj {
+    // true if both objects are of the same class, whatever their instance
+    pub fn is_same_class(&self, other: Eoj) -> bool {
+        self.class_group_code == other.class_group_code && self.class_code == other.class_code
+    }
+}
+
 impl fmt::Debug for Eoj {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Eoj")
@@ -119,7 +350,7 @@ impl fmt::Debug for EDataFormat1 {
         f.debug_struct("EDataType1")
          .field("seoj", &self.seoj)
          .field("deoj", &self.deoj)
-         .field("esv", &format_args!("{:#x}", self.esv))
+         .field("esv", &self.esv)
          .field("opc", &format_args!("{:#x}", self.opc))
          .field("props", &self.props)
          .finish()
@@ -163,7 +394,7 @@ impl Into<Bytes> for EDataFormat1 {
 
         bytes.put::<Bytes>(self.seoj.into());
         bytes.put::<Bytes>(self.deoj.into());
-        bytes.put_u8(self.esv);
+        bytes.put_u8(self.esv.into());
         bytes.put_u8(self.opc);
         for prop in self.props {
             bytes.put::<Bytes>(prop.into());
@@ -176,7 +407,7 @@ impl Into<Bytes> for EData {
     fn into(self) -> Bytes {
         match self {
             EData::EDataFormat1(data) => data.into(),
-            EData::InvalidEData(data) => data,
+            EData::EDataFormat2(data) => data,
         }
     }
 }
@@ -205,7 +436,7 @@ mod tests {
             edata: EData::EDataFormat1(EDataFormat1 {
                 seoj: EOJ_MANAGEMENT_CONTROLLER,
                 deoj: EOJ_HOUSING_LOW_VOLTAGE_SMART_METER,
-                esv: Esv::PROP_READ,
+                esv: Esv::Get,
                 opc: 0x01,
                 props: vec![EDataProperty {
                     epc: EpcLowVoltageSmartMeter::INSTANTANEOUS_ENERGY,
@@ -220,4 +451,73 @@ mod tests {
         assert_eq!(bytes, Bytes::from_static(b"\x10\x81\x00\x01\x05\xFF\x01\x02\x88\x01\x62\x01\xE7\x00"));
     }
 
+    #[test]
+    fn test_echonet_lite_format2_as_bytes() {
+        let data = EchonetLite {
+            ehd: EHd {
+                ehd1: EHD1_ECHONET_LITE,
+                ehd2: EHD2_FORMAT2,
+                tid: 0x0002,
+            },
+            edata: EData::EDataFormat2(Bytes::from_static(b"\x01\x02\x03")),
+        };
+
+        let bytes: Bytes = data.into();
+
+        assert_eq!(bytes, Bytes::from_static(b"\x10\x82\x00\x02\x01\x02\x03"));
+    }
+
+    #[test]
+    fn test_esv() {
+        for code in 0x00..=0xFF {
+            assert_eq!(u8::from(Esv::from(code)), code);
+        }
+
+        assert_eq!(Esv::from(0x73), Esv::Inf);
+        assert_eq!(Esv::from(0x62), Esv::Get);
+        assert_eq!(Esv::from(0x6F), Esv::Unknown(0x6F));
+
+        assert!(Esv::Get.is_request());
+        assert!(Esv::GetRes.is_response());
+        assert!(Esv::GetSna.is_error());
+        assert!(Esv::Infc.is_notification());
+        assert!(!Esv::InfcRes.is_notification());
+        assert!(Esv::Unknown(0x6F).is_request());
+        assert!(!Esv::Unknown(0x00).is_request() && !Esv::Unknown(0x00).is_response() && !Esv::Unknown(0x00).is_error());
+    }
+
+    #[test]
+    fn test_decode_instance_list() {
+        assert_eq!(decode_instance_list(&b"\x01\x02\x88\x01"[..]), Some(vec![EOJ_HOUSING_LOW_VOLTAGE_SMART_METER]));
+        assert_eq!(decode_instance_list(&b"\x02\x02\x88\x01"[..]), None);
+    }
+
+    #[test]
+    fn test_property_map_list() {
+        let map = PropertyMap::decode(&b"\x04\x80\xe7\xea\xeb"[..]).unwrap();
+        assert_eq!(map.iter().collect::<Vec<_>>(), vec![0x80, 0xe7, 0xea, 0xeb]);
+        assert!(map.contains(0xe7));
+        assert!(!map.contains(0xe8));
+
+        assert_eq!(std::convert::Into::<Bytes>::into(map), Bytes::from_static(b"\x04\x80\xe7\xea\xeb"));
+
+        assert_eq!(PropertyMap::decode(&b"\x04\x80\xe7"[..]), None);
+        assert_eq!(PropertyMap::decode(&b""[..]), None);
+    }
+
+    #[test]
+    fn test_property_map_bitmap() {
+        // 0x80-0x8F, 0x9D-0x9F, 0xD3, 0xD7, 0xE0-0xE5, 0xE7, 0xEA
+        let edt = b"\x1d\x41\x41\x41\x61\x41\x41\x01\x61\x01\x01\x41\x01\x01\x03\x03\x03";
+        let map = PropertyMap::decode(&edt[..]).unwrap();
+        let mut expected: Vec<u8> = (0x80..=0x8F).collect();
+        expected.extend([0x9D, 0x9E, 0x9F, 0xD3, 0xD7, 0xE0, 0xE1, 0xE2, 0xE3, 0xE4, 0xE5, 0xE7, 0xEA]);
+        assert_eq!(map.iter().collect::<Vec<_>>(), expected);
+
+        assert_eq!(std::convert::Into::<Bytes>::into(map), Bytes::copy_from_slice(&edt[..]));
+
+        // count does not match the bitmap
+        assert_eq!(PropertyMap::decode(&b"\x11\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00"[..]), None);
+    }
+
 }
\ No newline at end of file
@@ -0,0 +1,108 @@
+use bytes::{Buf, Bytes, BytesMut};
+
+use crate::dialect::Dialect;
+use crate::parser::{parser, Response};
+
+// input that does not complete a response within this many bytes is given up
+const MAX_PENDING_BYTES: usize = 8192;
+
+#[derive(Debug, PartialEq)]
+pub enum Frame {
+    Response(Response),
+    // input no parser understands, up to and including the next CRLF
+    Discarded(Bytes),
+}
+
+// splits the byte stream of the module into responses.
+// Unrecognized input is skipped line by line, so a bad line can not stop the reader.
+#[derive(Debug, Default)]
+pub struct Framer {
+    buf: BytesMut,
+    // formats of the module, known only after SKVER
+    pub dialect: Dialect,
+}
+
+impl Framer {
+    pub fn extend(&mut self, data: &[u8]) {
+        self.buf.extend_from_slice(data);
+    }
+
+    // the next response or discarded line, `None` until more data arrives
+    pub fn next_frame(&mut self) -> Option<Frame> {
+        if self.buf.is_empty() {
+            return None;
+        }
+
+        match parser(&self.buf, self.dialect) {
+            Ok((rest, response)) => {
+                let consumed = self.buf.len() - rest.len();
+                self.buf.advance(consumed);
+                Some(Frame::Response(response))
+            },
+            Err(nom::Err::Incomplete(_)) if self.buf.len() <= MAX_PENDING_BYTES => None,
+            Err(nom::Err::Incomplete(_)) => Some(self.discard_line().unwrap_or_else(|| self.discard_all())),
+            Err(_) => self.discard_line(),
+        }
+    }
+
+    // wait for the CRLF when the line is not complete yet, unless it is too long already
+    fn discard_line(&mut self) -> Option<Frame> {
+        match self.buf.windows(2).position(|w| w == b"\r\n") {
+            Some(pos) => Some(Frame::Discarded(self.buf.split_to(pos + 2).freeze())),
+            None if self.buf.len() > MAX_PENDING_BYTES => Some(self.discard_all()),
+            None => None,
+        }
+    }
+
+    fn discard_all(&mut self) -> Frame {
+        Frame::Discarded(self.buf.split().freeze())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_frame() {
+        let mut framer = Framer::default();
+        assert_eq!(framer.next_frame(), None);
+
+        framer.extend(b"SKRESET\r\nOK\r\nSKRESET\r\nOK\r\nSKRES");
+        assert_eq!(framer.next_frame(), Some(Frame::Response(Response::SkReset)));
+        assert_eq!(framer.next_frame(), Some(Frame::Response(Response::SkReset)));
+        assert_eq!(framer.next_frame(), None);
+
+        framer.extend(b"ET\r\nOK\r\n");
+        assert_eq!(framer.next_frame(), Some(Frame::Response(Response::SkReset)));
+        assert_eq!(framer.next_frame(), None);
+    }
+
+    #[test]
+    fn test_resynchronize() {
+        let mut framer = Framer::default();
+
+        // line noise at boot, then a line we do not understand
+        framer.extend(b"\x00\xff\x12garbage");
+        assert_eq!(framer.next_frame(), None);
+        framer.extend(b"\r\nHELLO WORLD\r\nSKRESET\r\nOK\r\n");
+        assert_eq!(framer.next_frame(), Some(Frame::Discarded(Bytes::from_static(b"\x00\xff\x12garbage\r\n"))));
+        assert_eq!(framer.next_frame(), Some(Frame::Discarded(Bytes::from_static(b"HELLO WORLD\r\n"))));
+        assert_eq!(framer.next_frame(), Some(Frame::Response(Response::SkReset)));
+        assert_eq!(framer.next_frame(), None);
+    }
+
+    #[test]
+    fn test_give_up_pending() {
+        let mut framer = Framer::default();
+
+        // ERXUDP announcing far more data than will ever come
+        framer.extend(b"ERXUDP FE80:0000:0000:0000:0123:4567:89ab:cdef FE80:0000:0000:0000:0123:4567:89ab:cdef 0E1A 0E1A 0123456789ABCDEF 1 FFFF \r\n");
+        assert_eq!(framer.next_frame(), None);
+
+        framer.extend(&[b'x'; MAX_PENDING_BYTES + 1]);
+        assert!(matches!(framer.next_frame(), Some(Frame::Discarded(line)) if line.starts_with(b"ERXUDP")));
+        assert!(matches!(framer.next_frame(), Some(Frame::Discarded(rest)) if rest.len() == MAX_PENDING_BYTES + 1));
+        assert_eq!(framer.next_frame(), None);
+    }
+}
@@ -0,0 +1,184 @@
+use std::error::Error;
+use std::time::Duration;
+
+use log::{info, warn};
+
+use crate::Capabilities;
+use crate::cache::{CachedGauge, ValueCache};
+use crate::echonet_lite::{Eoj, EDataFormat1, EDataProperty, EpcHighVoltageSmartMeter, Esv};
+use crate::history::INTERVAL_SECS;
+use crate::scheduler::Timing;
+use crate::session::{Session, get_property};
+use crate::value::{self, EnergyUnit, MeterValue};
+
+// each request carries the units, so that the values can be converted
+const UNIT_PROPERTIES: [u8; 3] = [
+    EpcHighVoltageSmartMeter::DEMAND_UNIT,
+    EpcHighVoltageSmartMeter::COEFFICIENT,
+    EpcHighVoltageSmartMeter::CUMULATIVE_ACTIVE_ENERGY_UNIT,
+];
+
+// the demand and the fixed-time values change only after each half hour
+const AFTER_HALF_HOUR: Timing = Timing::Aligned {
+    period: Duration::from_secs(INTERVAL_SECS as u64),
+    delay: Duration::from_secs(5 * 60),
+};
+
+// (property, timing, priority)
+pub const SCHEDULE: [(u8, Timing, u8); 6] = [
+    (EpcHighVoltageSmartMeter::CUMULATIVE_ACTIVE_ENERGY, Timing::Every(Duration::from_secs(5 * 60)), 0),
+    (EpcHighVoltageSmartMeter::DEMAND_FIXED_TIME, AFTER_HALF_HOUR, 1),
+    (EpcHighVoltageSmartMeter::CUMULATIVE_ACTIVE_ENERGY_FIXED_TIME, AFTER_HALF_HOUR, 1),
+    (EpcHighVoltageSmartMeter::CUMULATIVE_REACTIVE_ENERGY_LAG_FIXED_TIME, AFTER_HALF_HOUR, 1),
+    (EpcHighVoltageSmartMeter::MONTHLY_MAXIMUM_DEMAND, AFTER_HALF_HOUR, 2),
+    (EpcHighVoltageSmartMeter::CUMULATIVE_MAXIMUM_DEMAND, AFTER_HALF_HOUR, 2),
+];
+
+// registered only once a high-voltage meter has been found, so low-voltage sites do not export zeros
+pub struct HighVoltageMetrics {
+    monthly_maximum_demand: CachedGauge,
+    cumulative_maximum_demand: CachedGauge,
+    demand_fixed_time: CachedGauge,
+    cumulative_active_energy: CachedGauge,
+    cumulative_active_energy_fixed_time: CachedGauge,
+    cumulative_reactive_energy_lag_fixed_time: CachedGauge,
+}
+
+impl HighVoltageMetrics {
+    pub fn register(cache: &ValueCache) -> HighVoltageMetrics {
+        let metrics = HighVoltageMetrics {
+            monthly_maximum_demand: cache.gauge("high_voltage_monthly_maximum_demand_kilowatts", "Maximum demand of this month in kW"),
+            cumulative_maximum_demand: cache.gauge("high_voltage_cumulative_maximum_demand_kilowatts", "Cumulative maximum demand in kW"),
+            demand_fixed_time: cache.gauge("high_voltage_demand_fixed_time_kilowatts", "30 minutes average demand at the latest fixed time in kW"),
+            cumulative_active_energy: cache.counter_vec("high_voltage_active_energy_kwh_total", "Cumulative active energy in kWh", &[]).with_label_values(&[]),
+            cumulative_active_energy_fixed_time: cache.gauge("high_voltage_active_energy_fixed_time_kwh", "Cumulative active energy at the latest fixed time in kWh"),
+            cumulative_reactive_energy_lag_fixed_time: cache.gauge("high_voltage_reactive_energy_lag_fixed_time_kvarh", "Cumulative lagging reactive energy at the latest fixed time in kvarh"),
+        };
+        for (gauge, epc) in [
+            (&metrics.monthly_maximum_demand, EpcHighVoltageSmartMeter::MONTHLY_MAXIMUM_DEMAND),
+            (&metrics.cumulative_maximum_demand, EpcHighVoltageSmartMeter::CUMULATIVE_MAXIMUM_DEMAND),
+            (&metrics.demand_fixed_time, EpcHighVoltageSmartMeter::DEMAND_FIXED_TIME),
+            (&metrics.cumulative_active_energy, EpcHighVoltageSmartMeter::CUMULATIVE_ACTIVE_ENERGY),
+            (&metrics.cumulative_active_energy_fixed_time, EpcHighVoltageSmartMeter::CUMULATIVE_ACTIVE_ENERGY_FIXED_TIME),
+            (&metrics.cumulative_reactive_energy_lag_fixed_time, EpcHighVoltageSmartMeter::CUMULATIVE_REACTIVE_ENERGY_LAG_FIXED_TIME),
+        ] {
+            if let Some((_, timing, _)) = SCHEDULE.iter().find(|(scheduled, _, _)| *scheduled == epc) {
+                gauge.refreshed_every(timing.period());
+            }
+        }
+        metrics
+    }
+}
+
+// read the demand and energy properties of a high-voltage meter and export them
+// Returns the properties exported.
+pub fn poll(session: &mut Session, eoj: Eoj, capabilities: &Capabilities, epcs: &[u8], metrics: &HighVoltageMetrics) -> Result<Vec<u8>, Box<dyn Error>> {
+    let props: Vec<EDataProperty> = epcs.iter().copied()
+        .chain(UNIT_PROPERTIES)
+        .filter(|&epc| capabilities.can_get(epc))
+        .map(get_property)
+        .collect();
+    if props.is_empty() {
+        return Err("none of the properties can be read".into());
+    }
+    let r = session.request(eoj, Esv::Get, props)?;
+    export(&r, metrics)
+}
+
+// the values in a response to Get, converted with the units in the same response
+fn export(r: &EDataFormat1, metrics: &HighVoltageMetrics) -> Result<Vec<u8>, Box<dyn Error>> {
+    if r.esv != Esv::GetRes {
+        return Err(format!("failed to read high-voltage smartmeter: {:?}", r).into());
+    }
+
+    let mut values = vec![];
+    for prop in &r.props {
+        match value::decode(r.seoj, prop, EnergyUnit::default()) {
+            Ok(value) => values.push((prop.epc, value)),
+            Err(e) => warn!("invalid property of high-voltage smartmeter: {}", e),
+        }
+    }
+
+    // both the demand and the energy are multiplied by the coefficient (D3) and their own unit
+    let mut coefficient = 1.0;
+    let mut demand_unit = 1.0;
+    let mut energy_unit = 1.0;
+    for (epc, value) in &values {
+        match (*epc, value) {
+            (EpcHighVoltageSmartMeter::COEFFICIENT, MeterValue::Coefficient(c)) => coefficient = *c as f64,
+            (EpcHighVoltageSmartMeter::DEMAND_UNIT, MeterValue::CumulativeEnergyUnit(unit)) => demand_unit = *unit,
+            (EpcHighVoltageSmartMeter::CUMULATIVE_ACTIVE_ENERGY_UNIT, MeterValue::CumulativeEnergyUnit(unit)) => energy_unit = *unit,
+            _ => {
+                // ignore
+            }
+        }
+    }
+    let kw = |raw: u32| raw as f64 * demand_unit * coefficient;
+    let kwh = |raw: u32| raw as f64 * energy_unit * coefficient;
+
+    let mut exported = vec![];
+    for (epc, value) in values {
+        match (epc, value) {
+            (EpcHighVoltageSmartMeter::MONTHLY_MAXIMUM_DEMAND, MeterValue::Unsigned(raw)) => {
+                metrics.monthly_maximum_demand.set(kw(raw));
+                exported.push(epc);
+            },
+            (EpcHighVoltageSmartMeter::CUMULATIVE_MAXIMUM_DEMAND, MeterValue::Unsigned(raw)) => {
+                metrics.cumulative_maximum_demand.set(kw(raw));
+                exported.push(epc);
+            },
+            (EpcHighVoltageSmartMeter::DEMAND_FIXED_TIME, MeterValue::FixedTimeValue { at, raw }) => {
+                info!("high-voltage demand: at={} kw={}", at, kw(raw));
+                metrics.demand_fixed_time.set(kw(raw));
+                exported.push(epc);
+            },
+            (EpcHighVoltageSmartMeter::CUMULATIVE_ACTIVE_ENERGY, MeterValue::Unsigned(raw)) => {
+                metrics.cumulative_active_energy.set(kwh(raw));
+                exported.push(epc);
+            },
+            (EpcHighVoltageSmartMeter::CUMULATIVE_ACTIVE_ENERGY_FIXED_TIME, MeterValue::FixedTimeValue { at, raw }) => {
+                info!("high-voltage active energy: at={} kwh={}", at, kwh(raw));
+                metrics.cumulative_active_energy_fixed_time.set(kwh(raw));
+                exported.push(epc);
+            },
+            // the reactive energy shares the unit of the active energy
+            (EpcHighVoltageSmartMeter::CUMULATIVE_REACTIVE_ENERGY_LAG_FIXED_TIME, MeterValue::FixedTimeValue { at, raw }) => {
+                info!("high-voltage reactive energy (lag): at={} kvarh={}", at, kwh(raw));
+                metrics.cumulative_reactive_energy_lag_fixed_time.set(kwh(raw));
+                exported.push(epc);
+            },
+            _ => {
+                // ignore
+            }
+        }
+    }
+
+    Ok(exported)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use crate::echonet_lite::{EOJ_HOUSING_HIGH_VOLTAGE_SMART_METER, EOJ_MANAGEMENT_CONTROLLER};
+
+    fn response(esv: Esv, props: Vec<(u8, &'static [u8])>) -> EDataFormat1 {
+        EDataFormat1 {
+            seoj: EOJ_HOUSING_HIGH_VOLTAGE_SMART_METER,
+            deoj: EOJ_MANAGEMENT_CONTROLLER,
+            esv,
+            opc: props.len() as u8,
+            props: props.into_iter().map(|(epc, edt)| EDataProperty { epc, pdc: edt.len() as u8, edt: Bytes::from_static(edt) }).collect(),
+        }
+    }
+
+    #[test]
+    fn test_export() {
+        let metrics = HighVoltageMetrics::register(&ValueCache::default());
+        let r = response(Esv::GetRes, vec![(0xE0, b"\x00\x00\x12\x34"), (0xD3, b"\x00\x00\x00\x01"), (0xE1, b"\x01")]);
+        assert_eq!(export(&r, &metrics).unwrap(), vec![0xE0]);
+
+        let r = response(Esv::GetSna, vec![(0xE0, b"\x00\x00\x12\x34"), (0xC1, b"")]);
+        assert!(export(&r, &metrics).is_err());
+    }
+}
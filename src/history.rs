@@ -0,0 +1,156 @@
+use std::collections::BTreeMap;
+
+use bytes::Buf;
+
+// fixed-time readings (EA/EB) are taken every 30 minutes
+pub const INTERVAL_SECS: i64 = 30 * 60;
+pub const DAY_SECS: i64 = 24 * 60 * 60;
+// the meter clock is in JST
+const JST_OFFSET_SECS: i64 = 9 * 60 * 60;
+
+// E5 accepts 0x00 (today) ..= 0x63 (99 days ago)
+pub const HISTORY_MAX_DAYS: i64 = 99;
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub enum Direction {
+    Normal,
+    Reverse,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct FixedTimeReading {
+    pub direction: Direction,
+    pub at: i64, // unix time
+    pub kwh: f64,
+}
+
+// number of days since 1970-01-01 of the given civil date
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let yoe = year - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+// 7 bytes of date and time used by EA/EB: year(2) month day hour minute second
+pub fn meter_time_to_unix(mut buf: &[u8]) -> Option<i64> {
+    if buf.len() != 7 {
+        return None;
+    }
+    let year = buf.get_u16() as i64;
+    let (month, day) = (buf.get_u8() as i64, buf.get_u8() as i64);
+    let (hour, minute, second) = (buf.get_u8() as i64, buf.get_u8() as i64, buf.get_u8() as i64);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+
+    let local = days_from_civil(year, month, day) * DAY_SECS + hour * 60 * 60 + minute * 60 + second;
+    Some(local - JST_OFFSET_SECS)
+}
+
+// index of the meter-local day that contains `at`
+pub fn meter_day(at: i64) -> i64 {
+    (at + JST_OFFSET_SECS).div_euclid(DAY_SECS)
+}
+
+pub fn meter_day_start(day: i64) -> i64 {
+    day * DAY_SECS - JST_OFFSET_SECS
+}
+
+// fixed-time readings received so far, keyed by direction and timestamp.
+// `None` marks an interval that turned out to be unrecoverable.
+#[derive(Debug, Default)]
+pub struct FixedTimeHistory {
+    readings: BTreeMap<(Direction, i64), Option<f64>>,
+}
+
+impl FixedTimeHistory {
+    // returns false when the interval was already stored
+    pub fn insert(&mut self, reading: FixedTimeReading) -> bool {
+        let entry = self.readings.entry((reading.direction, reading.at)).or_insert(None);
+        entry.replace(reading.kwh).is_none()
+    }
+
+    pub fn mark_lost(&mut self, direction: Direction, at: i64) {
+        self.readings.entry((direction, at)).or_insert(None);
+    }
+
+    pub fn latest(&self, direction: Direction) -> Option<FixedTimeReading> {
+        self.readings
+            .range((direction, i64::MIN)..=(direction, i64::MAX))
+            .rev()
+            .find_map(|(&(direction, at), &kwh)| kwh.map(|kwh| FixedTimeReading { direction, at, kwh }))
+    }
+
+    // intervals between the first stored reading and `until` that have neither a reading nor are marked as lost
+    pub fn missing(&self, direction: Direction, until: i64) -> Vec<i64> {
+        let mut stored = self.readings
+            .range((direction, i64::MIN)..=(direction, until))
+            .map(|(&(_, at), _)| at);
+        let first = match stored.next() {
+            Some(first) => first,
+            None => return vec![],
+        };
+
+        let mut missing = vec![];
+        let mut expected = first + INTERVAL_SECS;
+        for at in stored.chain(std::iter::once(until + INTERVAL_SECS)) {
+            while expected < at && expected <= until {
+                missing.push(expected);
+                expected += INTERVAL_SECS;
+            }
+            expected = expected.max(at + INTERVAL_SECS);
+        }
+        missing
+    }
+
+    // drop readings older than `before`; they can no longer be recovered from the meter anyway
+    pub fn prune(&mut self, before: i64) {
+        self.readings.retain(|&(_, at), _| at >= before);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2023-04-15 00:00:00 JST
+    const DAY_START: i64 = 1681484400;
+
+    #[test]
+    fn test_meter_day() {
+        assert_eq!(meter_day_start(meter_day(DAY_START)), DAY_START);
+        assert_eq!(meter_day_start(meter_day(DAY_START + DAY_SECS - 1)), DAY_START);
+        assert_eq!(meter_day(DAY_START + DAY_SECS), meter_day(DAY_START) + 1);
+    }
+
+    #[test]
+    fn test_missing() {
+        let mut history = FixedTimeHistory::default();
+        assert_eq!(history.missing(Direction::Normal, DAY_START), vec![]);
+
+        for i in [0, 1, 4, 5] {
+            assert!(history.insert(FixedTimeReading { direction: Direction::Normal, at: DAY_START + i * INTERVAL_SECS, kwh: i as f64 }));
+        }
+        assert!(!history.insert(FixedTimeReading { direction: Direction::Normal, at: DAY_START, kwh: 0.0 }));
+
+        assert_eq!(history.missing(Direction::Normal, DAY_START + 5 * INTERVAL_SECS), vec![DAY_START + 2 * INTERVAL_SECS, DAY_START + 3 * INTERVAL_SECS]);
+        assert_eq!(history.missing(Direction::Normal, DAY_START + 7 * INTERVAL_SECS), vec![DAY_START + 2 * INTERVAL_SECS, DAY_START + 3 * INTERVAL_SECS, DAY_START + 6 * INTERVAL_SECS, DAY_START + 7 * INTERVAL_SECS]);
+        assert_eq!(history.missing(Direction::Reverse, DAY_START + 7 * INTERVAL_SECS), vec![]);
+        assert_eq!(history.latest(Direction::Normal).map(|r| r.at), Some(DAY_START + 5 * INTERVAL_SECS));
+
+        history.mark_lost(Direction::Normal, DAY_START + 2 * INTERVAL_SECS);
+        history.mark_lost(Direction::Normal, DAY_START + 7 * INTERVAL_SECS);
+        assert_eq!(history.missing(Direction::Normal, DAY_START + 7 * INTERVAL_SECS), vec![DAY_START + 3 * INTERVAL_SECS, DAY_START + 6 * INTERVAL_SECS]);
+        assert_eq!(history.latest(Direction::Normal).map(|r| r.at), Some(DAY_START + 5 * INTERVAL_SECS));
+
+        // a recovered reading replaces the lost mark
+        assert!(history.insert(FixedTimeReading { direction: Direction::Normal, at: DAY_START + 7 * INTERVAL_SECS, kwh: 7.0 }));
+        assert_eq!(history.latest(Direction::Normal).map(|r| r.at), Some(DAY_START + 7 * INTERVAL_SECS));
+
+        history.prune(DAY_START + 4 * INTERVAL_SECS);
+        assert_eq!(history.missing(Direction::Normal, DAY_START + 5 * INTERVAL_SECS), vec![]);
+    }
+}
@@ -0,0 +1,124 @@
+use crate::echonet_lite::{EDataProperty, EpcSuperClass};
+
+// what the meter tells about itself, read once after joining
+#[derive(Debug, PartialEq, Eq, Default, Clone)]
+pub struct MeterIdentity {
+    pub manufacturer: Option<String>,
+    pub product_code: Option<String>,
+    pub serial_number: Option<String>,
+    pub appendix_release: Option<char>,
+    pub echonet_version: Option<String>,
+}
+
+// 12 bytes of ASCII, padded with NUL or spaces
+fn decode_ascii(edt: &[u8]) -> Option<String> {
+    if !edt.is_ascii() {
+        return None;
+    }
+    let s = String::from_utf8_lossy(edt);
+    let s = s.trim_end_matches(['\0', ' ']);
+    if s.is_empty() {
+        return None;
+    }
+    Some(s.to_string())
+}
+
+impl MeterIdentity {
+    // properties of the meter object take precedence, the node profile only fills the gaps
+    pub fn update(&mut self, props: &[EDataProperty], node_profile: bool) {
+        for prop in props {
+            match (prop.epc, prop.edt.len()) {
+                (EpcSuperClass::MANUFACTURER_CODE, 3) if self.manufacturer.is_none() => {
+                    self.manufacturer = Some(format!("{:02X}{:02X}{:02X}", prop.edt[0], prop.edt[1], prop.edt[2]));
+                },
+                (EpcSuperClass::PRODUCT_CODE, 12) if self.product_code.is_none() => {
+                    self.product_code = decode_ascii(&prop.edt);
+                },
+                (EpcSuperClass::PRODUCTION_NUMBER, 12) if self.serial_number.is_none() => {
+                    self.serial_number = decode_ascii(&prop.edt);
+                },
+                // node profile: major and minor version of ECHONET Lite, followed by the supported message formats
+                (EpcSuperClass::VERSION_INFORMATION, 4) if node_profile => {
+                    self.echonet_version = Some(format!("{}.{}", prop.edt[0], prop.edt[1]));
+                },
+                // device object: release of the APPENDIX the object conforms to, e.g. 0x00 0x00 'J' 0x00
+                (EpcSuperClass::VERSION_INFORMATION, 4) if !node_profile && prop.edt[2].is_ascii_uppercase() => {
+                    self.appendix_release = Some(prop.edt[2] as char);
+                },
+                _ => {
+                    // ignore
+                }
+            }
+        }
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.manufacturer.is_some() && self.product_code.is_some() && self.serial_number.is_some() && self.echonet_version.is_some()
+    }
+
+    // true if both identities name a meter and they are not the same one
+    pub fn is_replaced_by(&self, other: &MeterIdentity) -> bool {
+        match ((&self.manufacturer, &self.serial_number), (&other.manufacturer, &other.serial_number)) {
+            ((Some(m1), Some(s1)), (Some(m2), Some(s2))) => m1 != m2 || s1 != s2,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+
+    fn prop(epc: u8, edt: &'static [u8]) -> EDataProperty {
+        EDataProperty {
+            epc,
+            pdc: edt.len() as u8,
+            edt: Bytes::from_static(edt),
+        }
+    }
+
+    #[test]
+    fn test_update() {
+        let mut identity = MeterIdentity::default();
+        identity.update(&[
+            prop(EpcSuperClass::MANUFACTURER_CODE, b"\x00\x00\x16"),
+            prop(EpcSuperClass::PRODUCTION_NUMBER, b"000012345678"),
+            prop(EpcSuperClass::VERSION_INFORMATION, b"\x00\x00J\x00"),
+            prop(EpcSuperClass::PRODUCT_CODE, b""),
+        ], false);
+        assert!(!identity.is_complete());
+
+        identity.update(&[
+            prop(EpcSuperClass::MANUFACTURER_CODE, b"\x00\x00\x08"),
+            prop(EpcSuperClass::PRODUCT_CODE, b"ABC-123\0\0\0\0\0"),
+            prop(EpcSuperClass::VERSION_INFORMATION, b"\x01\x0d\x01\x00"),
+        ], true);
+        assert!(identity.is_complete());
+
+        assert_eq!(identity, MeterIdentity {
+            manufacturer: Some("000016".to_string()),
+            product_code: Some("ABC-123".to_string()),
+            serial_number: Some("000012345678".to_string()),
+            appendix_release: Some('J'),
+            echonet_version: Some("1.13".to_string()),
+        });
+    }
+
+    #[test]
+    fn test_is_replaced_by() {
+        let meter = MeterIdentity {
+            manufacturer: Some("000016".to_string()),
+            serial_number: Some("000012345678".to_string()),
+            ..Default::default()
+        };
+        let replaced = MeterIdentity {
+            serial_number: Some("000087654321".to_string()),
+            ..meter.clone()
+        };
+
+        assert!(!meter.is_replaced_by(&meter.clone()));
+        assert!(meter.is_replaced_by(&replaced));
+        assert!(!meter.is_replaced_by(&MeterIdentity::default()));
+    }
+}
@@ -1,4 +1,4 @@
-use bytes::{BytesMut, BufMut, Bytes, Buf};
+use bytes::Bytes;
 use log::{info, debug, error, warn};
 use std::fs::OpenOptions;
 use std::io;
@@ -7,42 +7,117 @@ use std::sync::{Arc, Mutex};
 use std::thread::JoinHandle;
 use std::{net::SocketAddr, io::Read, io::Write};
 use std::error::Error;
-use std::time::Duration;
-use std::sync::mpsc::{channel, Receiver};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::collections::BTreeMap;
 
 use env_logger::{
     Builder,
     Env, Target,
 };
-use prometheus_exporter::prometheus::register_gauge;
+use prometheus_exporter::prometheus::{Gauge, GaugeVec, HistogramVec, IntCounter, IntCounterVec};
 use rppal::uart::{Parity, Uart, Queue};
 
+mod airtime;
+mod cache;
 mod parser;
-use parser::{parser, PanDesc, IpAddr};
+use parser::{PanDesc, IpAddr, Event, SendResult, FailCode, ChannelEnergy};
 mod command;
+mod config;
+mod dialect;
 use command::Command;
 mod echonet_lite;
+mod high_voltage;
+mod metrics;
+mod framing;
+mod history;
+mod identity;
+mod session;
+mod register;
+mod registry;
+mod scheduler;
+mod status;
+mod value;
 
 use crate::parser::{Response};
-use crate::echonet_lite::{EchonetLite, EData, EDataFormat1, EOJ_HOUSING_LOW_VOLTAGE_SMART_METER, EDataProperty, EpcLowVoltageSmartMeter};
+use crate::cache::{CachedGauge, CachedGaugeVec, TimestampedGaugeVec, ValueCache};
+use crate::config::Config;
+use crate::dialect::Dialect;
+use crate::register::{Register, Sreg};
+use crate::framing::{Framer, Frame};
+use crate::echonet_lite::{EDataFormat1, Eoj, EOJ_HOUSING_LOW_VOLTAGE_SMART_METER, EOJ_HOUSING_HIGH_VOLTAGE_SMART_METER, EOJ_NODE_PROFILE, EDataProperty, EpcSuperClass, EpcNodeProfile, EpcLowVoltageSmartMeter, Esv, PropertyMap};
+use crate::high_voltage::HighVoltageMetrics;
+use crate::metrics::{LegacyMetrics, Naming};
+use crate::identity::MeterIdentity;
+use crate::scheduler::{Scheduler, Timing};
+use crate::session::{Session, get_property};
+use crate::status::SharedStatus;
+use crate::history::{Direction, FixedTimeHistory, FixedTimeReading, HISTORY_MAX_DAYS, DAY_SECS, INTERVAL_SECS};
+use crate::value::{Amperes, EnergyUnit, MeterValue, Watts};
 
 
 #[derive(Debug)]
 struct UartReader {
     inner: Arc<Mutex<Uart>>,
     is_closed: Arc<AtomicBool>,
+    dialect: Arc<Mutex<Dialect>>,
+    timer: Arc<Mutex<CommandTimer>>,
 }
 
 #[derive(Debug)]
 struct UartWriter {
     inner: Arc<Mutex<Uart>>,
     is_closed: Arc<AtomicBool>,
+    dialect: Arc<Mutex<Dialect>>,
+    timer: Arc<Mutex<CommandTimer>>,
+}
+
+// the command not answered yet and when it was written, so that only its own answer is timed
+#[derive(Debug, Default)]
+struct CommandTimer {
+    pending: Option<(&'static str, Instant)>,
+}
+
+impl CommandTimer {
+    fn sent(&mut self, command: &'static str, at: Instant) {
+        self.pending = Some((command, at));
+    }
+
+    // the round trip, if `r` answers the pending command
+    fn answered(&mut self, r: &Response, at: Instant) -> Option<Duration> {
+        let (command, sent_at) = self.pending?;
+        let answers = match r {
+            Response::Ok => true,
+            Response::Fail { command: echo, .. } => echo.as_deref().is_none_or(|echo| echo.starts_with(command)),
+            r => r.command() == Some(command),
+        };
+        if !answers {
+            return None;
+        }
+        self.pending = None;
+        Some(at.saturating_duration_since(sent_at))
+    }
 }
 
 fn split_uart(uart: Uart) -> (UartReader, UartWriter) {
     let inner = Arc::new(Mutex::new(uart));
     let is_closed = Arc::new(AtomicBool::new(false));
-    (UartReader { inner: inner.clone(), is_closed: is_closed.clone() }, UartWriter { inner, is_closed })
+    let dialect = Arc::new(Mutex::new(Dialect::default()));
+    let timer = Arc::new(Mutex::new(CommandTimer::default()));
+    (
+        UartReader { inner: inner.clone(), is_closed: is_closed.clone(), dialect: dialect.clone(), timer: timer.clone() },
+        UartWriter { inner, is_closed, dialect, timer },
+    )
+}
+
+impl UartReader {
+    fn dialect(&self) -> Dialect {
+        *self.dialect.lock().expect("failed to acuire lock")
+    }
+
+    fn answered(&self, r: &Response) -> Option<Duration> {
+        self.timer.lock().expect("failed to acuire lock").answered(r, Instant::now())
+    }
 }
 
 impl Read for UartReader {
@@ -66,10 +141,24 @@ impl UartWriter {
     fn send_command(&mut self, cmd: Command) -> Result<(), Box<dyn Error>> {
         debug!("sending command: {:?}", cmd);
 
-        let cmd: Bytes = cmd.into();
+        let dialect = *self.dialect.lock().expect("failed to acuire lock");
+        let name = cmd.name();
+        let cmd: Bytes = cmd.encode(dialect);
         self.write_all(&cmd)?;
+        self.timer.lock().expect("failed to acuire lock").sent(name, Instant::now());
         Ok(())
     }
+
+    // both the commands and the parser of the reader follow the new dialect
+    fn set_dialect(&mut self, dialect: Dialect) {
+        *self.dialect.lock().expect("failed to acuire lock") = dialect;
+    }
+}
+
+impl session::CommandWriter for UartWriter {
+    fn send_command(&mut self, cmd: Command) -> Result<(), Box<dyn Error>> {
+        UartWriter::send_command(self, cmd)
+    }
 }
 
 impl Write for UartWriter {
@@ -99,22 +188,25 @@ impl Drop for UartWriter {
     }
 }
 
+// the next response, or the error of the command if the module rejected it
+fn recv_response(receiver: &mut Receiver<Response>) -> Result<Response, Box<dyn Error>> {
+    Ok(command::check_response(receiver.recv()?)?)
+}
+
 fn active_scan(sensor: &mut UartWriter, receiver: &mut Receiver<Response>) -> Result<PanDesc, Box<dyn Error>> {
     sensor.send_command(Command::ActiveScan { duration: 6 })?;
-    let r = receiver.recv()?;
-    if ! matches!(r, Response::SkScan { ..}) {
+    let r = recv_response(receiver)?;
+    if ! matches!(r, Response::SkScan { ..} | Response::Ok) {
         return Err("SKSCAN failed".into());
     }
 
     let mut tmp = Err("unable to find sensor within duration".into());
     loop {
-        let r = receiver.recv()?;
+        let r = recv_response(receiver)?;
         match r {
-            Response::Event { num, sender, param } => {
-                if num == 0x22 {
-                    return tmp;
-                }
-            }
+            Response::Event { event: Event::ActiveScanDone, .. } => {
+                return tmp;
+            },
             Response::EPanDesc(pandesc) => {
                 tmp = Ok(pandesc);
             },
@@ -131,12 +223,12 @@ fn wait_for_connect(sensor: &mut UartWriter, receiver: &mut Receiver<Response>)
             return Err("connect timeout".into());
         }
 
-        let r = receiver.recv()?;
+        let r = recv_response(receiver)?;
         match r {
-            Response::Event { num: 0x24, .. } => {
+            Response::Event { event: Event::PanaConnectFailed, .. } => {
                 return Err("failed to connect to PANA".into());
             },
-            Response::Event { num: 0x25, .. } => {
+            Response::Event { event: Event::PanaConnected, .. } => {
                 return Ok(());
             }
             _ => {
@@ -145,118 +237,399 @@ fn wait_for_connect(sensor: &mut UartWriter, receiver: &mut Receiver<Response>)
     }
 }
 
-fn send_initialize_command_sequence(writer: &mut UartWriter, receiver: &mut Receiver<Response>) -> Result<IpAddr, Box<dyn Error>> {
+fn read_register(writer: &mut UartWriter, receiver: &mut Receiver<Response>, sreg: u8) -> Result<Register, Box<dyn Error>> {
+    writer.send_command(Command::SkSregRead { sreg })?;
+    match recv_response(receiver)? {
+        Response::ESReg { val } => Ok(Register::read(sreg, val)?),
+        _ => Err(format!("SKSREG S{:02X} failed", sreg).into()),
+    }
+}
+
+// write a register and read it back, the echo back alone does not tell the value was taken
+fn write_register(writer: &mut UartWriter, receiver: &mut Receiver<Response>, register: Register) -> Result<(), Box<dyn Error>> {
+    register.validate()?;
+    writer.send_command(Command::SkSreg { register })?;
+    let r = recv_response(receiver)?;
+    if ! matches!(r, Response::SkSreg { ..} | Response::Ok) {
+        return Err(format!("SKSREG S{:02X} failed", register.sreg()).into());
+    }
+
+    let actual = read_register(writer, receiver, register.sreg())?;
+    if actual != register {
+        return Err(format!("register S{:02X} is {:?} after writing {:?}", register.sreg(), actual, register).into());
+    }
+    Ok(())
+}
+// bit 0 of ROPT/WOPT: ERXUDP payload in hex digits
+const OPT_ASCII_PAYLOAD: u8 = 0x01;
+
+// make the encoding of the ERXUDP payload the configured one.
+// WOPT is saved in the flash of the module, so it is written only when it differs.
+fn set_payload_encoding(writer: &mut UartWriter, receiver: &mut Receiver<Response>, config: &Config) -> Result<(), Box<dyn Error>> {
+    writer.send_command(Command::ROpt)?;
+    let mode = match command::check_response(receiver.recv()?) {
+        Ok(Response::ROpt { mode }) => mode,
+        // older firmware has no ROPT and always sends raw bytes, the parser accepts both anyway
+        Err(e) if e.code == FailCode::UnsupportedCommand => {
+            warn!("ROPT is not supported, keep the ERXUDP payload encoding as is");
+            return Ok(());
+        },
+        Ok(_) => {
+            return Err("ROPT failed".into());
+        },
+        Err(e) => {
+            return Err(e.into());
+        },
+    };
+
+    let ascii = mode & OPT_ASCII_PAYLOAD != 0;
+    if ascii == config.ascii_payload {
+        return Ok(());
+    }
+    let mode = if config.ascii_payload { mode | OPT_ASCII_PAYLOAD } else { mode & !OPT_ASCII_PAYLOAD };
+    info!("change the ERXUDP payload encoding: ascii={}", config.ascii_payload);
+    writer.send_command(Command::WOpt { mode })?;
+    let r = recv_response(receiver)?;
+    if ! matches!(r, Response::WOpt { ..} | Response::Ok) {
+        return Err("WOPT failed".into());
+    }
+    Ok(())
+}
+
+// SKTABLE mode
+const SKTABLE_NEIGHBOR_CACHE: u8 = 0x02;
+
+// the Wi-SUN module, as SKVER, SKAPPVER and SKINFO report it
+#[derive(Debug, Default, Clone)]
+struct ModuleInfo {
+    version: String,
+    app_version: String,
+    addr64: String,
+    ipaddr: IpAddr,
+}
+
+impl ModuleInfo {
+    // the firmware generation tells the formats of the commands and responses
+    fn dialect(&self) -> Dialect {
+        Dialect::from_version(&self.version).unwrap_or_else(|| {
+            warn!("unknown SKSTACK IP version {}, assuming {:?}", self.version, Dialect::default());
+            Dialect::default()
+        })
+    }
+}
+
+fn read_module_info(writer: &mut UartWriter, receiver: &mut Receiver<Response>) -> Result<ModuleInfo, Box<dyn Error>> {
+    let mut module = ModuleInfo::default();
+
+    writer.send_command(Command::SkVer)?;
+    match recv_response(receiver)? {
+        Response::EVer { version } => module.version = version,
+        _ => return Err("SKVER failed".into()),
+    }
+
+    writer.send_command(Command::SkAppVer)?;
+    match recv_response(receiver)? {
+        Response::EAppVer { version } => module.app_version = version,
+        _ => return Err("SKAPPVER failed".into()),
+    }
+
+    writer.send_command(Command::SkInfo)?;
+    match recv_response(receiver)? {
+        Response::EInfo { addr64, ipaddr, .. } => {
+            module.addr64 = addr64;
+            module.ipaddr = ipaddr;
+        },
+        _ => return Err("SKINFO failed".into()),
+    }
+
+    Ok(module)
+}
+
+fn export_module_info(module: &ModuleInfo, module_info: &GaugeVec, status: &SharedStatus) {
+    status.lock().expect("failed to acuire lock").module = Some(module.clone());
+    module_info.reset();
+    module_info.with_label_values(&[&module.version, &module.app_version, &module.addr64]).set(1.0);
+}
+
+// duration of the energy detection on each channel: 0.01 s * (2^n + 1)
+const ED_SCAN_DURATION: u8 = 6;
+
+// noise on every channel
+fn ed_scan(writer: &mut UartWriter, receiver: &mut Receiver<Response>) -> Result<Vec<ChannelEnergy>, Box<dyn Error>> {
+    writer.send_command(Command::EdScan { duration: ED_SCAN_DURATION })?;
+    let r = recv_response(receiver)?;
+    if ! matches!(r, Response::SkScan { ..} | Response::Ok) {
+        return Err("SKSCAN failed".into());
+    }
+
+    // EVENT 1F is followed by the result
+    loop {
+        if let Response::EEdScan(channels) = recv_response(receiver)? {
+            return Ok(channels);
+        }
+    }
+}
+
+// radio conditions of the link to the smartmeter
+struct LinkMetrics {
+    ed_scan_level: GaugeVec,
+    join_rssi: Gauge,
+    pan_info: GaugeVec,
+    // each step of the initialization, and all of it as "total"
+    initialize_duration: HistogramVec,
+}
+
+impl LinkMetrics {
+    fn register(naming: &Naming) -> LinkMetrics {
+        LinkMetrics {
+            ed_scan_level: naming.gauge_vec("ed_scan_level", "Energy detected on each channel by the last ED scan, in the unit of LQI", &["channel"]),
+            join_rssi: naming.gauge("join_rssi_dbm", "RSSI of the beacon of the smartmeter when joined in dBm"),
+            pan_info: naming.gauge_vec("pan_info", "PAN of the smartmeter joined", &["channel", "pan_id", "addr"]),
+            initialize_duration: naming.histogram_vec("initialize_duration_seconds", "Time taken by the initialization of the Wi-SUN module and the join", &["step"], INITIALIZE_BUCKETS.to_vec()),
+        }
+    }
+
+    // observe the step started at `started`, and start the next one
+    fn finish_step(&self, step: &str, started: &mut Instant) {
+        self.initialize_duration.with_label_values(&[step]).observe(started.elapsed().as_secs_f64());
+        *started = Instant::now();
+    }
+}
+
+fn export_pan(pan_desc: &PanDesc, metrics: &LinkMetrics) {
+    metrics.join_rssi.set(pan_desc.rssi_dbm());
+    metrics.pan_info.reset();
+    metrics.pan_info.with_label_values(&[
+        &pan_desc.channel.to_string(),
+        &format!("{:04X}", pan_desc.pan_id),
+        &pan_desc.addr,
+    ]).set(1.0);
+}
+
+fn export_ed_scan(channels: &[ChannelEnergy], ed_scan_level: &GaugeVec) {
+    ed_scan_level.reset();
+    for c in channels {
+        ed_scan_level.with_label_values(&[&c.channel.to_string()]).set(c.level as f64);
+    }
+}
+
+// reset the module and bring it into the state the configuration asks for
+fn prepare_module(writer: &mut UartWriter, receiver: &mut Receiver<Response>, config: &Config) -> Result<ModuleInfo, Box<dyn Error>> {
     // reset
     writer.send_command(Command::SkReset)?;
-    let r = receiver.recv()?;
-    if ! matches!(r, Response::SkReset) {
+    let r = recv_response(receiver)?;
+    if ! matches!(r, Response::SkReset | Response::Ok) {
         return Err("SKRESET failed".into());
     }
 
+    // set echo back explicitly, whatever the module was saved with
+    write_register(writer, receiver, Register::EchoBack(config.echo_back))?;
+
+    set_payload_encoding(writer, receiver, config)?;
+
+    let module = read_module_info(writer, receiver)?;
+    info!("module: {:?}", module);
+    let dialect = match config.dialect {
+        Some(dialect) => dialect,
+        None => module.dialect(),
+    };
+    info!("dialect: {:?}", dialect);
+    writer.set_dialect(dialect);
+
+    Ok(module)
+}
+
+// every command is answered either with its echo back or, when echo back is off, with a bare OK
+fn send_initialize_command_sequence(writer: &mut UartWriter, receiver: &mut Receiver<Response>, config: &Config, link_metrics: &LinkMetrics) -> Result<(IpAddr, ModuleInfo), Box<dyn Error>> {
+    let mut started = Instant::now();
+    let module = prepare_module(writer, receiver, config)?;
+    link_metrics.finish_step("prepare", &mut started);
+
+    if config.ed_scan {
+        match ed_scan(writer, receiver) {
+            Ok(channels) => {
+                info!("ED scan: {:?}", channels);
+                export_ed_scan(&channels, &link_metrics.ed_scan_level);
+                link_metrics.finish_step("ed_scan", &mut started);
+            },
+            Err(e) => {
+                warn!("ED scan failed: {:?}", e);
+                started = Instant::now();
+            },
+        }
+    }
+
     // send id
     writer.send_command(Command::SkSetRbid { id: B_ID })?;
-    let r = receiver.recv()?;
+    let r = recv_response(receiver)?;
 
-    if ! matches!(r, Response::SkSetRbid { ..}) {
+    if ! matches!(r, Response::SkSetRbid { ..} | Response::Ok) {
         return Err("SKSETRBID failed".into());
     }
 
     // send pw
     writer.send_command(Command::SkSetPwd { pwd: B_PW })?;
-    let r = receiver.recv()?;
-    if ! matches!(r, Response::SkSetPwd { ..} ) {
+    let r = recv_response(receiver)?;
+    if ! matches!(r, Response::SkSetPwd { ..} | Response::Ok) {
         return Err("SKSETPWD failed".into());
     }
+    link_metrics.finish_step("credentials", &mut started);
 
     let pan_desc = active_scan(writer, receiver)?;
-    debug!("pan_desc: {:?}", pan_desc);
+    info!("pan_desc: {:?}, rssi: {:.1} dBm", pan_desc, pan_desc.rssi_dbm());
+    export_pan(&pan_desc, link_metrics);
+    link_metrics.finish_step("active_scan", &mut started);
 
-    // set channel
-    writer.send_command(Command::SkSreg { sreg: 0x02, val: pan_desc.channel as u32 })?;
-    let r = receiver.recv()?;
-    if ! matches!(r, Response::SkSreg { ..} ) {
-        return Err("SKSREG failed".into());
-    }
-
-    // set pan id
-    writer.send_command(Command::SkSreg { sreg: 0x03, val: pan_desc.pan_id as u32 })?;
-    let r = receiver.recv()?;
-    if ! matches!(r, Response::SkSreg { ..} ) {
-        return Err("SKSREG failed".into());
-    }
+    write_register(writer, receiver, Register::Channel(pan_desc.channel))?;
+    write_register(writer, receiver, Register::PanId(pan_desc.pan_id))?;
 
     // convert addr
     writer.send_command(Command::SkLl64 { addr64: &pan_desc.addr })?;
-    let r = receiver.recv()?;
+    let r = recv_response(receiver)?;
     let ipv6_addr = match r {
-        Response::SkLl64 { ipaddr, .. } => ipaddr,
+        Response::SkLl64 { ipaddr, .. } | Response::Ipv6Addr(ipaddr) => ipaddr,
         _ => {
             return Err("SKLL64 failed".into());
         }
     };
+    link_metrics.finish_step("configure", &mut started);
 
     // connect to pana
     writer.send_command(Command::SkJoin { ipaddr: &ipv6_addr })?;
-    let r = receiver.recv()?;
-    if ! matches!(r, Response::SkJoin { ..} ) {
+    let r = recv_response(receiver)?;
+    if ! matches!(r, Response::SkJoin { ..} | Response::Ok) {
         return Err("SKJOIN failed".into());
     }
 
     wait_for_connect(writer, receiver)?;
+    link_metrics.finish_step("join", &mut started);
+
+    Ok((ipv6_addr, module))
+}
+
 
-    Ok(ipv6_addr)
+// metrics updated by the reader thread
+#[derive(Clone)]
+struct ReaderMetrics {
+    events: IntCounterVec,
+    failures: IntCounterVec,
+    discarded_bytes: IntCounter,
+    rssi: Gauge,
+    // command: the command to its echo back or result, sendto_event: SKSENDTO to EVENT 21,
+    // sendto_response: SKSENDTO to the ERXUDP with the response of the smartmeter
+    round_trip: HistogramVec,
 }
 
+const ROUND_TRIP_BUCKETS: [f64; 11] = [0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 20.0];
+const INITIALIZE_BUCKETS: [f64; 10] = [0.5, 1.0, 2.5, 5.0, 10.0, 20.0, 30.0, 60.0, 120.0, 300.0];
+
+impl ReaderMetrics {
+    fn register(naming: &Naming) -> ReaderMetrics {
+        ReaderMetrics {
+            events: naming.counter_vec("module_events_total", "# of events received from the Wi-SUN module", &["kind"]),
+            failures: naming.counter_vec("module_failures_total", "# of commands the Wi-SUN module rejected with FAIL", &["code"]),
+            discarded_bytes: naming.counter("discarded_bytes_total", "# of bytes from the Wi-SUN module which could not be parsed"),
+            rssi: naming.gauge("rssi_dbm", "RSSI of the last frame received from the smartmeter in dBm"),
+            round_trip: naming.histogram_vec("round_trip_seconds", "Time until the Wi-SUN module or the smartmeter answers", &["operation"], ROUND_TRIP_BUCKETS.to_vec()),
+        }
+    }
+}
+
+// an open module joined to the smartmeter: the writer, what the reader thread receives,
+// the address of the smartmeter, the module information and the reader thread
+type Initialized = (UartWriter, Receiver<Response>, IpAddr, ModuleInfo, JoinHandle<()>);
 
 // # cancellation
 // It is caller responsibility to ensure that the previous reader thread closes before calling initialize again.
 // By dropping thre writer, reader.read() will get error and then the reader thread closes.
 // Note that reader.read() yield something no later than reader timeout set by uart.set_read_mode().
 // So, if you drop the writer, you can successfully join the reader thread within the timeout.
-fn initialize() -> Result<(UartWriter, Receiver<Response>, IpAddr, JoinHandle<()>), Box<dyn Error>>  {
+fn initialize(config: &Config, metrics: &ReaderMetrics, link_metrics: &LinkMetrics) -> Result<Initialized, Box<dyn Error>>  {
+    let mut started = Instant::now();
+    let (mut writer, mut receiver, handle) = open_module(metrics)?;
+
+    let (ipv6_addr, module) = match send_initialize_command_sequence(&mut writer, &mut receiver, config, link_metrics) {
+        Ok(r) => {
+            link_metrics.finish_step("total", &mut started);
+            r
+        },
+        Err(e) => {
+            drop(writer);
+            handle.join().expect("failed to join the reader thread");
+            return Err(e)
+        }
+    };
+
+    Ok((writer, receiver, ipv6_addr, module, handle))
+}
+
+// the writer, what the reader thread receives, and the reader thread
+type OpenModule = (UartWriter, Receiver<Response>, JoinHandle<()>);
+
+// open the UART and start the reader thread
+fn open_module(metrics: &ReaderMetrics) -> Result<OpenModule, Box<dyn Error>> {
     let mut uart = Uart::with_path("/dev/ttyAMA0", 115200, Parity::None, 8, 1)?;
 
     // Configure read() to block until at least 1 byte is received or timeout elapsed
     uart.set_read_mode(0, Duration::from_millis(2000))?;
     uart.set_write_mode(true)?;
 
-    let (sender, mut receiver) = channel();
-    let (mut reader, mut writer) = split_uart(uart);
+    let (sender, receiver) = channel();
+    let (mut reader, writer) = split_uart(uart);
+    let metrics = metrics.clone();
 
     let handle = std::thread::spawn(move || {
-        let mut buf = BytesMut::with_capacity(1024);
+        let mut framer = Framer::default();
         loop {
             let mut b = [0; 1024];
 
             match reader.read(&mut b) {
                 Ok(n) if n > 0 => {
                     debug!("read: {:?}", &b[..n]);
-                    buf.put(&b[..n]);
+                    framer.extend(&b[..n]);
                 },
                 Err(e) => {
+                    // only I/O errors finish reading from device
                     error!("uart read error: {:?}", e);
                     break;
                 }
                 _ => {}
             }
 
-            debug!("current buf: {:?}", buf);
-            match parser(&buf) {
-                Ok((rest, line)) => {
-                    debug!("parsed response: {:?}", line);
-                    sender.send(line).unwrap();
-
-                    buf = BytesMut::from(rest);
-                },
-                Err(nom::Err::Incomplete(n)) => {
-                    // not enough data
-                    debug!("parse incomplate: {:?}", n);
-                },
-                Err(e) => {
-                    error!("parse error: {:?}", e);
-
-                    // finish reading from device
-                    break;
+            framer.dialect = reader.dialect();
+            while let Some(frame) = framer.next_frame() {
+                match frame {
+                    Frame::Response(line) => {
+                        debug!("parsed response: {:?}", line);
+                        // the echo back or the result of the last command, events come on their own
+                        if let Some(round_trip) = reader.answered(&line) {
+                            metrics.round_trip.with_label_values(&["command"]).observe(round_trip.as_secs_f64());
+                        }
+                        match &line {
+                            Response::Event { event, sender } => {
+                                info!("event from {}: {}", sender, event);
+                                metrics.events.with_label_values(&[event.kind()]).inc();
+                            },
+                            Response::Fail { command, code } => {
+                                warn!("{} failed: {}", command.as_deref().unwrap_or("command"), code);
+                                metrics.failures.with_label_values(&[&code.label()]).inc();
+                            },
+                            // only BP35C0 reports it for each frame
+                            Response::ERxUdp { rssi: Some(rssi), .. } => {
+                                metrics.rssi.set(*rssi as f64);
+                            },
+                            // carries EVENT 21
+                            Response::SkSendTo { .. } => {
+                                metrics.events.with_label_values(&[Event::UdpSent(SendResult::Success).kind()]).inc();
+                            },
+                            _ => {}
+                        }
+                        sender.send(line).unwrap();
+                    },
+                    Frame::Discarded(bytes) => {
+                        warn!("discarded unrecognized input: {:?}", bytes);
+                        metrics.discarded_bytes.inc_by(bytes.len() as u64);
+                    }
                 }
             }
         }
@@ -264,18 +637,490 @@ fn initialize() -> Result<(UartWriter, Receiver<Response>, IpAddr, JoinHandle<()
         drop(sender);
     });
 
-    let ipv6_addr = match send_initialize_command_sequence(&mut writer, &mut receiver) {
-        Ok(ipv6_addr) => ipv6_addr,
-        Err(e) => {
-            drop(writer);
-            handle.join().expect("failed to join the reader thread");
-            return Err(e)
+    Ok((writer, receiver, handle))
+}
+
+// `smartmeter-exporter ed-scan`: print the noise on every channel, to find a better place for the dongle
+fn run_ed_scan(config: &Config) -> Result<(), Box<dyn Error>> {
+    let (mut writer, mut receiver, handle) = open_module(&ReaderMetrics::register(&config.naming))?;
+    let result = prepare_module(&mut writer, &mut receiver, config)
+        .and_then(|_| ed_scan(&mut writer, &mut receiver));
+    drop(writer);
+    handle.join().expect("failed to join the reader thread");
+
+    println!("channel level    dBm");
+    for c in result? {
+        println!("{:>7} {:>5} {:>6.1}", c.channel, c.level, parser::lqi_to_dbm(c.level));
+    }
+    Ok(())
+}
+
+// properties the smartmeter reported in its property maps.
+// A map which could not be read is `None`, and then the access rules of the registry are assumed.
+#[derive(Debug)]
+struct Capabilities {
+    eoj: Eoj,
+    announce: Option<PropertyMap>,
+    set: Option<PropertyMap>,
+    get: Option<PropertyMap>,
+}
+
+impl Capabilities {
+    fn new(eoj: Eoj) -> Capabilities {
+        Capabilities {
+            eoj,
+            announce: None,
+            set: None,
+            get: None,
         }
+    }
+
+    fn can_set(&self, epc: u8) -> bool {
+        match &self.set {
+            Some(map) => map.contains(epc),
+            None => registry::property(self.eoj, epc).is_none_or(|property| property.access.set),
+        }
+    }
+
+    fn can_get(&self, epc: u8) -> bool {
+        match &self.get {
+            Some(map) => map.contains(epc),
+            None => registry::property(self.eoj, epc).is_none_or(|property| property.access.get),
+        }
+    }
+}
+
+// the smartmeter object the node profile lists in its instance list
+fn detect_meter(session: &mut Session) -> Result<Eoj, Box<dyn Error>> {
+    let r = session.request(EOJ_NODE_PROFILE, Esv::Get, vec![get_property(EpcNodeProfile::SELF_NODE_INSTANCE_LIST_S)])?;
+    let instances = r.props.iter()
+        .find(|prop| prop.epc == EpcNodeProfile::SELF_NODE_INSTANCE_LIST_S)
+        .and_then(|prop| echonet_lite::decode_instance_list(&prop.edt))
+        .ok_or("invalid instance list")?;
+    info!("instance list: {:?}", instances.iter().map(|&eoj| (eoj, registry::class(eoj).map(|class| class.name))).collect::<Vec<_>>());
+
+    instances.into_iter()
+        .find(|eoj| eoj.is_same_class(EOJ_HOUSING_LOW_VOLTAGE_SMART_METER) || eoj.is_same_class(EOJ_HOUSING_HIGH_VOLTAGE_SMART_METER))
+        .ok_or_else(|| "no smartmeter in the instance list".into())
+}
+
+fn read_capabilities(session: &mut Session, meter: Eoj) -> Result<Capabilities, Box<dyn Error>> {
+    let r = session.request(meter, Esv::Get, vec![
+        get_property(EpcSuperClass::STATUS_CHANGE_ANNOUNCEMENT_PROPERTY_MAP),
+        get_property(EpcSuperClass::SET_PROPERTY_MAP),
+        get_property(EpcSuperClass::GET_PROPERTY_MAP),
+    ])?;
+
+    let mut capabilities = Capabilities::new(meter);
+    for prop in r.props {
+        let map = PropertyMap::decode(&prop.edt);
+        match prop.epc {
+            EpcSuperClass::STATUS_CHANGE_ANNOUNCEMENT_PROPERTY_MAP => capabilities.announce = map,
+            EpcSuperClass::SET_PROPERTY_MAP => capabilities.set = map,
+            EpcSuperClass::GET_PROPERTY_MAP => capabilities.get = map,
+            _ => {
+                // ignore
+            }
+        }
+    }
+    Ok(capabilities)
+}
+
+fn export_capabilities(capabilities: &Capabilities, property_map: &GaugeVec, status: &SharedStatus) {
+    property_map.reset();
+    let mut status = status.lock().expect("failed to acuire lock");
+    status.meter = Some(capabilities.eoj);
+    status.property_maps.clear();
+    for (name, map) in [("announce", &capabilities.announce), ("set", &capabilities.set), ("get", &capabilities.get)] {
+        for epc in map.iter().flat_map(|map| map.iter()) {
+            property_map.with_label_values(&[name, &format!("0x{:02X}", epc)]).set(1.0);
+        }
+        status.property_maps.insert(name, map.as_ref().map(|map| map.iter().collect()));
+    }
+}
+
+const IDENTITY_PROPERTIES: [u8; 4] = [
+    EpcSuperClass::VERSION_INFORMATION,
+    EpcSuperClass::MANUFACTURER_CODE,
+    EpcSuperClass::PRODUCT_CODE,
+    EpcSuperClass::PRODUCTION_NUMBER,
+];
+
+// read the identity from the meter object, and fall back to the node profile for what the meter object lacks
+fn read_identity(session: &mut Session, meter: Eoj, capabilities: &Capabilities) -> Result<MeterIdentity, Box<dyn Error>> {
+    let mut identity = MeterIdentity::default();
+
+    let props: Vec<EDataProperty> = IDENTITY_PROPERTIES.into_iter()
+        .filter(|&epc| capabilities.can_get(epc))
+        .map(get_property)
+        .collect();
+    if !props.is_empty() {
+        let r = session.request(meter, Esv::Get, props)?;
+        identity.update(&r.props, false);
+    }
+
+    if !identity.is_complete() {
+        let props = IDENTITY_PROPERTIES.into_iter().map(get_property).collect();
+        let r = session.request(EOJ_NODE_PROFILE, Esv::Get, props)?;
+        identity.update(&r.props, true);
+    }
+
+    Ok(identity)
+}
+
+fn export_identity(identity: &MeterIdentity, smartmeter_info: &GaugeVec, status: &SharedStatus) {
+    status.lock().expect("failed to acuire lock").identity = Some(identity.clone());
+    smartmeter_info.reset();
+    smartmeter_info.with_label_values(&[
+        identity.manufacturer.as_deref().unwrap_or(""),
+        identity.product_code.as_deref().unwrap_or(""),
+        identity.serial_number.as_deref().unwrap_or(""),
+        &identity.appendix_release.map(String::from).unwrap_or_default(),
+        identity.echonet_version.as_deref().unwrap_or(""),
+    ]).set(1.0);
+}
+
+fn now_unix() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).expect("system clock is before 1970").as_secs() as i64
+}
+
+// when a unix time of the meter's clock was, as an instant
+fn instant_of(at: i64) -> Instant {
+    let age = Duration::from_secs((now_unix() - at).max(0) as u64);
+    Instant::now().checked_sub(age).unwrap_or_else(Instant::now)
+}
+
+// the meter takes a few minutes to update EA/EB after each half hour
+const FIXED_TIME_READ_DELAY_SECS: i64 = 5 * 60;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Task {
+    Get(u8),
+    // EA/EB with the backfill of missed intervals
+    SyncFixedTime,
+}
+
+// identity and property maps are read once per session, before polling starts
+const LOW_VOLTAGE_SCHEDULE: [(Task, Timing, u8); 5] = [
+    (Task::Get(EpcLowVoltageSmartMeter::INSTANTANEOUS_ENERGY), Timing::Every(Duration::from_secs(10)), 0),
+    (Task::Get(EpcLowVoltageSmartMeter::INSTANTANEOUS_CURRENT), Timing::Every(Duration::from_secs(30)), 1),
+    (Task::SyncFixedTime, Timing::Aligned { period: Duration::from_secs(INTERVAL_SECS as u64), delay: Duration::from_secs(FIXED_TIME_READ_DELAY_SECS as u64) }, 1),
+    (Task::Get(EpcLowVoltageSmartMeter::CUMULATIVE_ENERGY_NORMAL_DIRECTION), Timing::Every(Duration::from_secs(5 * 60)), 2),
+    (Task::Get(EpcLowVoltageSmartMeter::CUMULATIVE_ENERGY_REVERSE_DIRECTION), Timing::Every(Duration::from_secs(5 * 60)), 2),
+];
+
+// properties due together are requested in one frame, up to this many
+const MAX_PROPS_PER_FRAME: usize = 8;
+// still wake up this often to pick up notifications
+const MAX_WAIT_SECS: i64 = 10;
+// with read-on-scrape, how long a scrape waits for the read before it gets the cached values
+const SCRAPE_WAIT: Duration = Duration::from_secs(5);
+// read on scrape: what is polled at most this priority
+const SCRAPE_PRIORITY: u8 = 0;
+
+// hands every scrape to the poll loop, and holds its response until the loop is done with it
+fn spawn_scrape_listener(exporter: prometheus_exporter::Exporter) -> Receiver<Sender<()>> {
+    let (sender, receiver) = channel();
+    std::thread::spawn(move || loop {
+        let guard = exporter.wait_request();
+        let (done, wait) = channel();
+        if sender.send(done).is_err() {
+            break;
+        }
+        let _ = wait.recv_timeout(SCRAPE_WAIT);
+        drop(guard);
+    });
+    receiver
+}
+
+fn schedule(meter: Eoj, capabilities: &Capabilities, now: i64) -> Scheduler<Task> {
+    let mut scheduler = Scheduler::new(now as u64);
+    let tasks: Vec<(Task, Timing, u8)> = if meter.is_same_class(EOJ_HOUSING_HIGH_VOLTAGE_SMART_METER) {
+        high_voltage::SCHEDULE.into_iter().map(|(epc, timing, priority)| (Task::Get(epc), timing, priority)).collect()
+    } else {
+        LOW_VOLTAGE_SCHEDULE.to_vec()
     };
+    for (task, timing, priority) in tasks {
+        match task {
+            Task::Get(epc) if !capabilities.can_get(epc) => {
+                info!("not polled: {:?}", task);
+            },
+            Task::SyncFixedTime if !capabilities.can_get(EpcLowVoltageSmartMeter::CUMULATIVE_ENERGY_FIXED_TIME_NORMAL_DIRECTION)
+                && !capabilities.can_get(EpcLowVoltageSmartMeter::CUMULATIVE_ENERGY_FIXED_TIME_REVERSE_DIRECTION) => {
+                info!("not polled: {:?}", task);
+            },
+            task => scheduler.add(task, timing, priority, now),
+        }
+    }
+    scheduler
+}
+
+// how often a task runs while it succeeds, after which what it exports starts going stale.
+// `None` if it is not scheduled, i.e. what it exports never goes stale
+fn period(task: Task) -> Option<Duration> {
+    LOW_VOLTAGE_SCHEDULE.iter()
+        .find(|(scheduled, _, _)| *scheduled == task)
+        .map(|(_, timing, _)| timing.period())
+}
+
+struct LowVoltageMetrics {
+    instantaneous_energy: CachedGauge,
+    instantaneous_current: CachedGaugeVec,
+    cumulative_energy: CachedGaugeVec,
+}
+
+impl LowVoltageMetrics {
+    fn register(cache: &ValueCache) -> LowVoltageMetrics {
+        let metrics = LowVoltageMetrics {
+            instantaneous_energy: cache.gauge("power_watts", "Current Power Consumption in Watt"),
+            instantaneous_current: cache.gauge_vec("current_amperes", "Current in Ampere", &["phase"]),
+            cumulative_energy: cache.counter_vec("energy_kwh_total", "Cumulative Energy in kWh", &["direction"]),
+        };
+        if let Some(period) = period(Task::Get(EpcLowVoltageSmartMeter::INSTANTANEOUS_ENERGY)) {
+            metrics.instantaneous_energy.refreshed_every(period);
+        }
+        if let Some(period) = period(Task::Get(EpcLowVoltageSmartMeter::INSTANTANEOUS_CURRENT)) {
+            metrics.instantaneous_current.refreshed_every(period);
+        }
+        if let Some(period) = period(Task::Get(EpcLowVoltageSmartMeter::CUMULATIVE_ENERGY_NORMAL_DIRECTION)) {
+            metrics.cumulative_energy.refreshed_every(period);
+        }
+        metrics
+    }
+}
+
+// E1 and D3, which E0/E3 and the fixed-time readings are counted in
+fn read_energy_unit(session: &mut Session, meter: Eoj, capabilities: &Capabilities) -> Result<EnergyUnit, Box<dyn Error>> {
+    let props: Vec<EDataProperty> = [EpcLowVoltageSmartMeter::CUMULATIVE_ENERGY_UNIT, EpcLowVoltageSmartMeter::COEFFICIENT].into_iter()
+        .filter(|&epc| capabilities.can_get(epc))
+        .map(get_property)
+        .collect();
+    if props.is_empty() {
+        return Err("the unit of cumulative energy can not be read".into());
+    }
+    let r = session.request(meter, Esv::Get, props)?;
+
+    let mut unit = None;
+    // optional, 1 if the meter does not have it
+    let mut coefficient = 1;
+    for prop in &r.props {
+        match value::decode(r.seoj, prop, EnergyUnit::default()) {
+            Ok(MeterValue::CumulativeEnergyUnit(kwh)) => unit = Some(kwh),
+            Ok(MeterValue::Coefficient(c)) => coefficient = c,
+            Ok(_) => {
+                // ignore
+            },
+            Err(e) => warn!("invalid property of the unit of cumulative energy: {}", e),
+        }
+    }
+    let unit = unit.ok_or_else(|| format!("no unit of cumulative energy in {:?}", r))?;
+    Ok(EnergyUnit {
+        kwh: unit * coefficient as f64,
+    })
+}
 
-    Ok((writer, receiver, ipv6_addr, handle))
+// read instantaneous and cumulative values of a low-voltage meter and export them.
+// The cumulative energy is left out until its unit is known.
+// Returns the properties exported.
+fn poll_low_voltage(session: &mut Session, meter: Eoj, epcs: &[u8], unit: Option<EnergyUnit>, metrics: &LowVoltageMetrics) -> Result<Vec<u8>, Box<dyn Error>> {
+    let r = session.request(meter, Esv::Get, epcs.iter().copied().map(get_property).collect())?;
+    export_low_voltage(&r, unit, metrics)
 }
 
+fn export_low_voltage(r: &EDataFormat1, unit: Option<EnergyUnit>, metrics: &LowVoltageMetrics) -> Result<Vec<u8>, Box<dyn Error>> {
+    if r.esv != Esv::GetRes {
+        return Err(format!("failed to read energy: {:?}", r).into());
+    }
+    let mut exported = vec![];
+    for prop in &r.props {
+        match value::decode(r.seoj, prop, unit.unwrap_or_default()) {
+            Ok(MeterValue::InstantaneousPower(Watts(watts))) => {
+                metrics.instantaneous_energy.set(watts as f64);
+                exported.push(prop.epc);
+            },
+            Ok(MeterValue::InstantaneousCurrent { r: Amperes(r), t }) => {
+                metrics.instantaneous_current.with_label_values(&["r"]).set(r);
+                if let Some(Amperes(t)) = t {
+                    metrics.instantaneous_current.with_label_values(&["t"]).set(t);
+                }
+                exported.push(prop.epc);
+            },
+            Ok(MeterValue::CumulativeEnergy { direction, raw, unit: energy_unit }) if unit.is_some() => {
+                metrics.cumulative_energy.with_label_values(&[direction_label(direction)]).set(raw as f64 * energy_unit.kwh);
+                exported.push(prop.epc);
+            },
+            Ok(_) => {
+                // ignore
+            },
+            Err(e) => {
+                warn!("invalid response to energy request: {}", e);
+            }
+        }
+    }
+    Ok(exported)
+}
+
+struct FixedTimeMetrics {
+    cumulative_energy_fixed_time: CachedGaugeVec,
+    // every reading at its fixed time, including the recovered ones
+    readings: TimestampedGaugeVec,
+    backfill_readings: IntCounterVec,
+}
+
+fn direction_label(direction: Direction) -> &'static str {
+    match direction {
+        Direction::Normal => "normal",
+        Direction::Reverse => "reverse",
+    }
+}
+
+fn record_fixed_time_reading(history: &mut FixedTimeHistory, reading: FixedTimeReading, metrics: &FixedTimeMetrics, recovered: bool) {
+    if !history.insert(reading) {
+        return;
+    }
+    info!("fixed-time reading: direction={} at={} kwh={} recovered={}", direction_label(reading.direction), reading.at, reading.kwh, recovered);
+    metrics.readings.set_at(&[direction_label(reading.direction)], reading.at, reading.kwh);
+
+    if history.latest(reading.direction) == Some(reading) {
+        metrics.cumulative_energy_fixed_time
+            .with_label_values(&[direction_label(reading.direction)])
+            .set_as_of(reading.kwh, instant_of(reading.at));
+    }
+}
+
+// read the latest fixed-time readings and fetch intervals we missed (e.g. while reconnecting) from the meter's history.
+// Returns the latest readings.
+fn sync_fixed_time_readings(session: &mut Session, meter: Eoj, capabilities: &Capabilities, unit: EnergyUnit, history: &mut FixedTimeHistory, metrics: &FixedTimeMetrics) -> Result<Vec<FixedTimeReading>, Box<dyn Error>> {
+    let props: Vec<EDataProperty> = [
+        EpcLowVoltageSmartMeter::CUMULATIVE_ENERGY_FIXED_TIME_NORMAL_DIRECTION,
+        EpcLowVoltageSmartMeter::CUMULATIVE_ENERGY_FIXED_TIME_REVERSE_DIRECTION,
+    ].into_iter()
+        .filter(|&epc| capabilities.can_get(epc))
+        .map(get_property)
+        .collect();
+    if props.is_empty() {
+        return Err("none of the fixed-time readings can be read".into());
+    }
+    let r = session.request(meter, Esv::Get, props)?;
+    let read = decode_fixed_time_readings(&r, unit)?;
+    for &reading in &read {
+        record_fixed_time_reading(history, reading, metrics, false);
+    }
+
+    for direction in [Direction::Normal, Direction::Reverse] {
+        let until = match history.latest(direction) {
+            Some(latest) => latest.at,
+            None => continue,
+        };
+        let today = history::meter_day(until);
+        let epc = match direction {
+            Direction::Normal => EpcLowVoltageSmartMeter::HISTORICAL_CUMULATIVE_ENERGY_1_NORMAL_DIRECTION,
+            Direction::Reverse => EpcLowVoltageSmartMeter::HISTORICAL_CUMULATIVE_ENERGY_1_REVERSE_DIRECTION,
+        };
+        let can_backfill = capabilities.can_set(EpcLowVoltageSmartMeter::DAY_FOR_HISTORICAL_DATA_1) && capabilities.can_get(epc);
+
+        let mut missing_by_day: BTreeMap<i64, Vec<i64>> = BTreeMap::new();
+        for at in history.missing(direction, until) {
+            let days_ago = today - history::meter_day(at);
+            if !can_backfill || days_ago > HISTORY_MAX_DAYS {
+                warn!("fixed-time reading can not be recovered: direction={} at={}", direction_label(direction), at);
+                history.mark_lost(direction, at);
+                metrics.backfill_readings.with_label_values(&["lost"]).inc();
+                continue;
+            }
+            missing_by_day.entry(days_ago).or_default().push(at);
+        }
+
+        for (days_ago, missing) in missing_by_day {
+            info!("backfilling {} fixed-time readings from {} days ago: direction={}", missing.len(), days_ago, direction_label(direction));
+
+            let r = session.request(meter, Esv::SetC, vec![MeterValue::DayForHistoricalData(days_ago as u8).encode()?])?;
+            if r.esv != Esv::SetRes {
+                return Err(format!("failed to set day for historical data: {:?}", r).into());
+            }
+
+            let r = session.request(meter, Esv::Get, vec![get_property(epc)])?;
+            // a slot is lost only if the meter says it has no data, not if the history could not be read
+            let recovered = decode_historical_readings(&r, epc, days_ago, unit)?;
+            let start = history::meter_day_start(today - days_ago);
+
+            for at in missing {
+                match recovered.get(((at - start) / INTERVAL_SECS) as usize).copied().flatten() {
+                    Some(kwh) => {
+                        record_fixed_time_reading(history, FixedTimeReading { direction, at, kwh }, metrics, true);
+                        metrics.backfill_readings.with_label_values(&["recovered"]).inc();
+                    },
+                    None => {
+                        warn!("fixed-time reading is not available in the meter's history: direction={} at={}", direction_label(direction), at);
+                        history.mark_lost(direction, at);
+                        metrics.backfill_readings.with_label_values(&["lost"]).inc();
+                    }
+                }
+            }
+        }
+
+        history.prune(until - (HISTORY_MAX_DAYS + 1) * DAY_SECS);
+    }
+
+    Ok(read)
+}
+
+// the latest fixed-time readings in a response to Get EA/EB
+fn decode_fixed_time_readings(r: &EDataFormat1, unit: EnergyUnit) -> Result<Vec<FixedTimeReading>, Box<dyn Error>> {
+    if r.esv != Esv::GetRes {
+        return Err(format!("failed to read fixed-time readings: {:?}", r).into());
+    }
+    let mut readings = vec![];
+    for prop in &r.props {
+        match value::decode(r.seoj, prop, unit) {
+            Ok(MeterValue::FixedTimeReading(reading)) => readings.push(reading),
+            Ok(_) => {
+                // ignore
+            },
+            Err(e) => warn!("invalid fixed-time property: {}", e),
+        }
+    }
+    if readings.is_empty() {
+        return Err("none of the fixed-time readings can be decoded".into());
+    }
+    Ok(readings)
+}
+
+// a day of the meter's history in a response to Get E2/E4, one per 30 minutes
+fn decode_historical_readings(r: &EDataFormat1, epc: u8, days_ago: i64, unit: EnergyUnit) -> Result<Vec<Option<f64>>, Box<dyn Error>> {
+    if r.esv != Esv::GetRes {
+        return Err(format!("failed to read historical data: {:?}", r).into());
+    }
+    let prop = r.props.iter()
+        .find(|prop| prop.epc == epc)
+        .ok_or("historical data is missing in the response")?;
+    match value::decode(r.seoj, prop, unit)? {
+        MeterValue::HistoricalCumulativeEnergy { days_ago: day, kwh, .. } if day as i64 == days_ago => Ok(kwh),
+        value => Err(format!("historical data of another day: {:?}", value).into()),
+    }
+}
+
+// INF frames the smartmeter sends on its own, e.g. EA/EB every 30 minutes or the instance list after joining
+fn handle_notification(notification: &EDataFormat1, unit: Option<EnergyUnit>, low_voltage_metrics: &LowVoltageMetrics, history: &mut FixedTimeHistory, metrics: &FixedTimeMetrics) {
+    for prop in &notification.props {
+        if (notification.seoj, prop.epc) == (EOJ_NODE_PROFILE, EpcNodeProfile::INSTANCE_LIST_NOTIFICATION) {
+            let instances = echonet_lite::decode_instance_list(&prop.edt).unwrap_or_default();
+            info!("instance list: {:?}", instances.iter().map(|&eoj| (eoj, registry::class(eoj).map(|class| class.name))).collect::<Vec<_>>());
+            continue;
+        }
+        match value::decode(notification.seoj, prop, unit.unwrap_or_default()) {
+            Ok(MeterValue::InstantaneousPower(Watts(watts))) => {
+                low_voltage_metrics.instantaneous_energy.set(watts as f64);
+            },
+            Ok(MeterValue::FixedTimeReading(reading)) if unit.is_some() => {
+                record_fixed_time_reading(history, reading, metrics, false);
+            },
+            _ => {
+                debug!("ignore notified property {}: {:?}", registry::property(notification.seoj, prop.epc).map_or("unknown", |property| property.name), prop);
+            }
+        }
+    }
+}
 
 const B_ID: &str = std::env!("B_ID");
 const B_PW: &str = std::env!("B_PW");
@@ -296,100 +1141,440 @@ fn main() -> Result<(), Box<dyn Error>> {
     }
     builder.init();
 
+    let config = Config::from_env()?;
+    info!("config: {:?}", config);
+
+    if std::env::args().nth(1).as_deref() == Some("ed-scan") {
+        return run_ed_scan(&config);
+    }
+
     let addr_raw = "0.0.0.0:9186";
     let addr: SocketAddr = addr_raw.parse().expect("can not parse listen addr");
 
+    // without read-on-scrape nobody waits for requests, and they are served from the cache right away
     let exporter = prometheus_exporter::start(addr).expect("can not start exporter");
-    let duration = std::time::Duration::from_millis(10000);
-
-    let counter_error_initialize = register_gauge!("counter_error_initialize", "# of error when try to initialize sensor with PANA")
-        .expect("can not create gauge counter_error_initialize");
-    let counter_error_sksendto = register_gauge!("counter_error_sksendto", "# of error when sending data to sensor")
-        .expect("can not create gauge counter_error_sksendto");
-    let counter_success_initialize = register_gauge!("counter_success_initialize", "# of times client finished initialization")
-        .expect("can not create gauge counter_success_initialize");
-    let counter_request_energy = register_gauge!("counter_request_energy", "# of times client send energy request")
-        .expect("can not create gauge counter_request_energy");
-    let instantaneous_energy = register_gauge!("instantaneous_energy", "Current Power Consumption in Watt")
-        .expect("can not create gauge instantaneous_energy");
+    let (_exporter, scrapes) = match config.read_on_scrape {
+        Some(_) => (None, Some(spawn_scrape_listener(exporter))),
+        None => (Some(exporter), None),
+    };
+    let mut last_read_on_scrape: Option<Instant> = None;
+
+    let status = SharedStatus::default();
+    if let Some(addr) = config.status_addr {
+        status::serve(addr, status.clone())?;
+    }
+
+    let naming = &config.naming;
+    let initializations = naming.counter_vec("initializations_total", "# of attempts to initialize the Wi-SUN module and join the smartmeter", &["result"]);
+    let requests = naming.counter_vec("requests_total", "# of requests sent to the smartmeter", &["result"]);
+    let cache = ValueCache::register(config.staleness, naming.clone());
+    let low_voltage_metrics = LowVoltageMetrics::register(&cache);
+    let fixed_time_metrics = FixedTimeMetrics {
+        cumulative_energy_fixed_time: cache.gauge_vec("fixed_time_energy_kwh", "Cumulative Energy at the latest fixed time in kWh", &["direction"]),
+        readings: TimestampedGaugeVec::register(naming, "fixed_time_energy_slot_kwh", "Cumulative Energy at each fixed time in kWh, timestamped with the fixed time", &["direction"], Duration::from_secs(INTERVAL_SECS as u64)),
+        backfill_readings: naming.counter_vec("backfill_readings_total", "# of missed fixed-time readings, recovered from the meter's history or lost", &["result"]),
+    };
+    if let Some(period) = period(Task::SyncFixedTime) {
+        fixed_time_metrics.cumulative_energy_fixed_time.refreshed_every(period);
+    }
+    let property_map = naming.gauge_vec("property_map", "Properties reported in the smartmeter's property maps", &["map", "epc"]);
+    let smartmeter_info = naming.gauge_vec("info", "Identity of the smartmeter", &["manufacturer", "product_code", "serial_number", "appendix_release", "echonet_version"]);
+    let reader_metrics = ReaderMetrics::register(naming);
+    let link_metrics = LinkMetrics::register(naming);
+    let module_info = naming.gauge_vec("module_info", "Firmware and MAC address of the Wi-SUN module", &["version", "app_version", "addr64"]);
+    let transmission_limited = naming.gauge("transmission_limited", "1 while the module suspends sending by the transmission time limit (ARIB STD-T108)");
+    let airtime_budget_remaining = naming.gauge("airtime_budget_remaining_seconds", "Estimated transmission time left in the last hour in seconds");
+    let polls_suspended = naming.counter("polls_suspended_total", "# of polling rounds skipped to stay within the transmission time limit");
+    let last_read_timestamp = naming.gauge_vec("last_read_timestamp_seconds", "Unix time of the latest successful read of each property", &["epc"]);
+    let fixed_time_errors = naming.counter("fixed_time_errors_total", "# of error when reading or backfilling fixed-time readings");
+
+    if config.legacy_metrics {
+        let name = |name| naming.fq_name(name);
+        let mut legacy = LegacyMetrics::default();
+        legacy.alias("counter_error_initialize", "# of error when try to initialize sensor with PANA", &initializations, name("initializations_total")).select("result", "error");
+        legacy.alias("counter_success_initialize", "# of times client finished initialization", &initializations, name("initializations_total")).select("result", "success");
+        legacy.alias("counter_request_energy", "# of times client send energy request", &requests, name("requests_total")).sum_over("result");
+        legacy.alias("counter_error_sksendto", "# of error when sending data to sensor", &requests, name("requests_total")).select("result", "error");
+        legacy.alias("instantaneous_energy", "Current Power Consumption in Watt", &cache, name("power_watts"));
+        legacy.register();
+    }
+
+    // kept across reconnects, so that intervals missed during an outage can be detected
+    let mut fixed_time_history = FixedTimeHistory::default();
+    let mut last_identity = MeterIdentity::default();
+    let mut high_voltage_metrics = None;
 
     loop {
-        let (mut writer, mut receiver, ipv6_addr, handle) = match initialize() {
+        let (writer, receiver, ipv6_addr, module, handle) = match initialize(&config, &reader_metrics, &link_metrics) {
             Ok(ipv6_addr) => ipv6_addr,
             Err(e) => {
                 error!("unable to initialize smartmeter: {:?}", e);
                 std::thread::sleep(Duration::from_secs(30));
-                counter_error_initialize.inc();
+                initializations.with_label_values(&["error"]).inc();
                 continue;
             }
         };
-        counter_success_initialize.inc();
+        initializations.with_label_values(&["success"]).inc();
         info!("initialize completed");
+        export_module_info(&module, &module_info, &status);
+
+        let mut session = Session::new(writer, receiver, ipv6_addr, reader_metrics.round_trip.clone());
+
+        match session.command(Command::SkSregRead { sreg: Sreg::TRANSMISSION_TIME }) {
+            Ok(Response::ESReg { val }) => {
+                info!("transmission time in the last hour: {} ms", val);
+                session.record_airtime(Duration::from_millis(val as u64));
+            },
+            Ok(r) => warn!("unexpected response to SKSREG: {:?}", r),
+            Err(e) => warn!("unable to read the transmission time: {:?}", e),
+        }
+
+        // only for troubleshooting
+        match session.command(Command::SkTable { mode: SKTABLE_NEIGHBOR_CACHE }) {
+            Ok(Response::ENeighbor(neighbors)) => info!("neighbor cache: {:?}", neighbors),
+            Ok(r) => warn!("unexpected response to SKTABLE: {:?}", r),
+            Err(e) => warn!("unable to read the neighbor cache: {:?}", e),
+        }
+
+        let meter = match detect_meter(&mut session) {
+            Ok(meter) => meter,
+            Err(e) => {
+                warn!("unable to detect the class of the smartmeter, assuming a low-voltage one: {:?}", e);
+                EOJ_HOUSING_LOW_VOLTAGE_SMART_METER
+            }
+        };
+        let is_high_voltage = meter.is_same_class(EOJ_HOUSING_HIGH_VOLTAGE_SMART_METER);
+        info!("smartmeter: {:?}", meter);
+
+        let capabilities = match read_capabilities(&mut session, meter) {
+            Ok(capabilities) => capabilities,
+            Err(e) => {
+                warn!("unable to read property maps, assuming the access rules of the registry: {:?}", e);
+                Capabilities::new(meter)
+            }
+        };
+        info!("capabilities: {:?}", capabilities);
+        export_capabilities(&capabilities, &property_map, &status);
+
+        match read_identity(&mut session, meter, &capabilities) {
+            Ok(identity) => {
+                info!("identity: {:?}", identity);
+                if last_identity.is_replaced_by(&identity) {
+                    warn!("smartmeter has been replaced: {:?} -> {:?}", last_identity, identity);
+                    fixed_time_history = FixedTimeHistory::default();
+                    fixed_time_metrics.cumulative_energy_fixed_time.reset();
+                    fixed_time_metrics.readings.reset();
+                }
+                export_identity(&identity, &smartmeter_info, &status);
+                last_identity = identity;
+            },
+            Err(e) => {
+                warn!("unable to read identity of the smartmeter: {:?}", e);
+            }
+        }
+
+        let mut scheduler = schedule(meter, &capabilities, now_unix());
+        // E1 and D3 of the low-voltage meter, read once per session before anything counted in them
+        let mut energy_unit = None;
 
         // main loop
         'main: loop {
-            let _guard = exporter.wait_duration(duration);
-            if let Err(e) = writer.send_command(Command::SendEnergyRequest { ipaddr: &ipv6_addr }) {
-                error!("failed to send command: {:?}", e);
-                counter_error_sksendto.inc();
+            let wait = scheduler.next_due().map_or(MAX_WAIT_SECS, |next| (next - now_unix()).clamp(0, MAX_WAIT_SECS));
+            let wait = Duration::from_secs(wait as u64);
+            let mut pending_scrapes = vec![];
+            match &scrapes {
+                Some(scrapes) => match scrapes.recv_timeout(wait) {
+                    Ok(done) => {
+                        pending_scrapes.push(done);
+                        pending_scrapes.extend(scrapes.try_iter());
+                        let min_interval = config.read_on_scrape.unwrap_or_default();
+                        if last_read_on_scrape.is_none_or(|at| at.elapsed() >= min_interval) {
+                            last_read_on_scrape = Some(Instant::now());
+                            scheduler.hurry(SCRAPE_PRIORITY, now_unix());
+                        }
+                    },
+                    Err(RecvTimeoutError::Timeout) => {},
+                    Err(RecvTimeoutError::Disconnected) => std::thread::sleep(wait),
+                },
+                None => std::thread::sleep(wait),
+            }
+
+            // EVENT 32 / 33 since the last round
+            if let Err(e) = session.poll() {
+                error!("reader thread closed when they encouter error: {:?}", e);
                 break 'main;
             }
-            counter_request_energy.inc();
-    
-
-            // wait response for energy request
-            'wait_response: loop {
-                let r = match receiver.recv() {
-                    Ok(r) => r,
-                    Err(e) => {
-                        error!("reader thread closed when they encouter error: {:?}", e);
-                        break 'main;
+            transmission_limited.set(session.is_transmission_limited() as i32 as f64);
+            airtime_budget_remaining.set(session.airtime_remaining().as_secs_f64());
+
+            let now = now_unix();
+            let due = scheduler.due(now);
+            // checked with a minimal frame
+            let sendable = match session.can_send(0) {
+                Ok(()) => true,
+                Err(e) if !due.is_empty() => {
+                    info!("skip polling: {}", e);
+                    polls_suspended.inc();
+                    false
+                },
+                Err(_) => false,
+            };
+
+            // wait for EVENT 33 or the budget to recover otherwise
+            if sendable {
+                // the limit may also come into force in the middle of the round, what is left stays due
+                let mut suspended = false;
+
+                if !is_high_voltage && energy_unit.is_none() {
+                    match read_energy_unit(&mut session, meter, &capabilities) {
+                        Ok(unit) => {
+                            info!("unit of cumulative energy: {:?}", unit);
+                            energy_unit = Some(unit);
+                        },
+                        Err(e) if session::is_fatal(e.as_ref()) => {
+                            error!("failed to read the unit of cumulative energy: {:?}", e);
+                            break 'main;
+                        },
+                        Err(e) => warn!("failed to read the unit of cumulative energy: {:?}", e),
                     }
-                };
-                info!("got response {:?}", r);
-    
-                match r {
-                    Response::SkSendTo{ result: 0x00, .. } => {
-                        debug!("send energy request success");
-                    },
-                    Response::SkSendTo{ result: _, .. } => {
-                        warn!("failed to send energy request: {:?}", r);
-                        counter_error_sksendto.inc();
-                        break 'wait_response;
-                    },
-                    Response::ERxUdp {
-                        data: EchonetLite {
-                            edata: EData::EDataFormat1(EDataFormat1 {
-                                seoj: EOJ_HOUSING_LOW_VOLTAGE_SMART_METER,
-                                props,
-                                ..
-                        }), .. }, ..
-                    } => {
-                        for prop in props {
-                            match prop {
-                                EDataProperty {
-                                    epc: EpcLowVoltageSmartMeter::INSTANTANEOUS_ENERGY,
-                                    pdc: 0x04,
-                                    mut edt,
-                                    ..
-                                } => {
-                                    let power = edt.get_u32();
-                                    instantaneous_energy.set(power as f64);
-                                },
-                                _ => {
-                                    // ignore
+                }
+
+                if due.contains(&Task::SyncFixedTime) {
+                    let result = match energy_unit {
+                        Some(unit) => sync_fixed_time_readings(&mut session, meter, &capabilities, unit, &mut fixed_time_history, &fixed_time_metrics),
+                        None => Err("the unit of cumulative energy is not known yet".into()),
+                    };
+                    match result {
+                        Ok(readings) => {
+                            scheduler.succeeded(Task::SyncFixedTime, now);
+                            // as of the fixed time, not of when it was read
+                            for reading in readings {
+                                let epc = match reading.direction {
+                                    Direction::Normal => EpcLowVoltageSmartMeter::CUMULATIVE_ENERGY_FIXED_TIME_NORMAL_DIRECTION,
+                                    Direction::Reverse => EpcLowVoltageSmartMeter::CUMULATIVE_ENERGY_FIXED_TIME_REVERSE_DIRECTION,
+                                };
+                                last_read_timestamp.with_label_values(&[&format!("0x{:02X}", epc)]).set(reading.at as f64);
+                            }
+                        },
+                        Err(e) if session::is_suspended(e.as_ref()) => {
+                            info!("skip syncing fixed-time readings: {}", e);
+                            polls_suspended.inc();
+                            suspended = true;
+                        },
+                        Err(e) => {
+                            error!("failed to sync fixed-time readings: {:?}", e);
+                            fixed_time_errors.inc();
+                            if session::is_fatal(e.as_ref()) {
+                                break 'main;
+                            }
+                            scheduler.failed(Task::SyncFixedTime, now);
+                        }
+                    }
+                }
+
+                let epcs: Vec<u8> = due.iter()
+                    .filter_map(|task| match task {
+                        Task::Get(epc) => Some(*epc),
+                        _ => None,
+                    })
+                    .collect();
+                for batch in epcs.chunks(MAX_PROPS_PER_FRAME) {
+                    if suspended {
+                        break;
+                    }
+                    let result = if is_high_voltage {
+                        let metrics = high_voltage_metrics.get_or_insert_with(|| HighVoltageMetrics::register(&cache));
+                        high_voltage::poll(&mut session, meter, &capabilities, batch, metrics)
+                    } else {
+                        poll_low_voltage(&mut session, meter, batch, energy_unit, &low_voltage_metrics)
+                    };
+                    match result {
+                        Ok(exported) => {
+                            requests.with_label_values(&["success"]).inc();
+                            // the others were not decoded, or not before the unit was known
+                            for &epc in batch {
+                                if exported.contains(&epc) {
+                                    scheduler.succeeded(Task::Get(epc), now);
+                                    last_read_timestamp.with_label_values(&[&format!("0x{:02X}", epc)]).set(now_unix() as f64);
+                                } else {
+                                    scheduler.failed(Task::Get(epc), now);
                                 }
                             }
+                        },
+                        Err(e) if session::is_fatal(e.as_ref()) => {
+                            error!("failed to send energy request: {:?}", e);
+                            requests.with_label_values(&["error"]).inc();
+                            break 'main;
+                        },
+                        Err(e) if session::is_suspended(e.as_ref()) => {
+                            info!("skip polling: {}", e);
+                            polls_suspended.inc();
+                            suspended = true;
+                        },
+                        Err(e) => {
+                            warn!("failed to send energy request: {:?}", e);
+                            requests.with_label_values(&["error"]).inc();
+                            for &epc in batch {
+                                scheduler.failed(Task::Get(epc), now);
+                            }
                         }
-                        break 'wait_response;
-                    },
-                    _ => {
-                        // ignore
                     }
                 }
             }
+
+            if let Err(e) = session.poll() {
+                error!("reader thread closed when they encouter error: {:?}", e);
+                break 'main;
+            }
+            for notification in session.take_notifications() {
+                handle_notification(&notification, energy_unit, &low_voltage_metrics, &mut fixed_time_history, &fixed_time_metrics);
+            }
+            for done in pending_scrapes {
+                let _ = done.send(());
+            }
         }
-        drop(writer);
+        drop(session.writer);
         handle.join().expect("failed to join the reader thread");
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prometheus_exporter::prometheus::{core::Collector, HistogramOpts, Opts};
+    use crate::echonet_lite::EOJ_MANAGEMENT_CONTROLLER;
+    use crate::parser::FailCode;
+
+    // 2023-04-15 00:00:00 JST
+    const DAY_START: i64 = 1681484400;
+
+    fn response(esv: Esv, props: Vec<(u8, &[u8])>) -> EDataFormat1 {
+        EDataFormat1 {
+            seoj: EOJ_HOUSING_LOW_VOLTAGE_SMART_METER,
+            deoj: EOJ_MANAGEMENT_CONTROLLER,
+            esv,
+            opc: props.len() as u8,
+            props: props.into_iter().map(|(epc, edt)| EDataProperty { epc, pdc: edt.len() as u8, edt: Bytes::copy_from_slice(edt) }).collect(),
+        }
+    }
+
+    fn fixed_time_metrics() -> FixedTimeMetrics {
+        let naming = Naming::default();
+        FixedTimeMetrics {
+            cumulative_energy_fixed_time: ValueCache::default().gauge_vec("fixed_time_energy_kwh", "Cumulative Energy at the latest fixed time in kWh", &["direction"]),
+            readings: TimestampedGaugeVec::new(&naming, "fixed_time_energy_slot_kwh", "Cumulative Energy at each fixed time in kWh", &["direction"], Duration::from_secs(INTERVAL_SECS as u64)),
+            backfill_readings: IntCounterVec::new(Opts::new("backfill_readings_total", "Backfill"), &["result"]).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_record_recovered_reading() {
+        let metrics = fixed_time_metrics();
+        let mut history = FixedTimeHistory::default();
+        record_fixed_time_reading(&mut history, FixedTimeReading { direction: Direction::Normal, at: DAY_START + 4 * INTERVAL_SECS, kwh: 4.0 }, &metrics, false);
+        record_fixed_time_reading(&mut history, FixedTimeReading { direction: Direction::Normal, at: DAY_START + 2 * INTERVAL_SECS, kwh: 2.0 }, &metrics, true);
+
+        let mfs = metrics.readings.collect();
+        let recovered = &mfs[0].get_metric()[0];
+        assert_eq!(recovered.get_timestamp_ms(), (DAY_START + 2 * INTERVAL_SECS) * 1000);
+        assert_eq!(recovered.get_gauge().get_value(), 2.0);
+        assert_eq!(mfs[0].get_metric()[1].get_timestamp_ms(), (DAY_START + 4 * INTERVAL_SECS) * 1000);
+    }
+
+    #[test]
+    fn test_export_low_voltage() {
+        let metrics = LowVoltageMetrics::register(&ValueCache::default());
+        let r = response(Esv::GetRes, vec![(0xE7, b"\x00\x00\x01\xf4"), (0xE0, b"\x00\x00\x12\x34")]);
+        assert_eq!(export_low_voltage(&r, Some(EnergyUnit { kwh: 0.1 }), &metrics).unwrap(), vec![0xE7, 0xE0]);
+        // the cumulative energy is not exported before the unit is known
+        assert_eq!(export_low_voltage(&r, None, &metrics).unwrap(), vec![0xE7]);
+
+        let r = response(Esv::GetSna, vec![(0xE7, b"\x00\x00\x01\xf4"), (0xE8, b"")]);
+        assert!(export_low_voltage(&r, Some(EnergyUnit { kwh: 0.1 }), &metrics).is_err());
+    }
+
+    #[test]
+    fn test_command_timer() {
+        let sent_at = Instant::now();
+        let mut timer = CommandTimer::default();
+        assert_eq!(timer.answered(&Response::Ok, sent_at), None);
+
+        timer.sent(Command::SkVer.name(), sent_at);
+        let event = Response::Event { event: Event::UdpSent(SendResult::Success), sender: "FE80:0000:0000:0000:021C:6400:030C:12A4".to_string() };
+        assert_eq!(timer.answered(&event, sent_at + Duration::from_millis(10)), None);
+        // the answer of another command, e.g. one which timed out
+        assert_eq!(timer.answered(&Response::EAppVer { version: "rev26e".to_string() }, sent_at + Duration::from_millis(20)), None);
+        assert_eq!(timer.answered(&Response::EVer { version: "1.2.10".to_string() }, sent_at + Duration::from_millis(30)), Some(Duration::from_millis(30)));
+        // answered once
+        assert_eq!(timer.answered(&Response::Ok, sent_at + Duration::from_millis(40)), None);
+
+        timer.sent(Command::SkSregRead { sreg: 0xFE }.name(), sent_at);
+        assert_eq!(timer.answered(&Response::Fail { command: Some("SKJOIN FE80".to_string()), code: FailCode::ExecutionFailed }, sent_at + Duration::from_millis(10)), None);
+        assert_eq!(timer.answered(&Response::Fail { command: Some("SKSREG SFE".to_string()), code: FailCode::ParameterOutOfRange }, sent_at + Duration::from_millis(20)), Some(Duration::from_millis(20)));
+
+        timer.sent(Command::SkInfo.name(), sent_at);
+        assert_eq!(timer.answered(&Response::Ok, sent_at + Duration::from_millis(50)), Some(Duration::from_millis(50)));
+    }
+
+    #[test]
+    fn test_finish_step() {
+        let metrics = LinkMetrics {
+            ed_scan_level: GaugeVec::new(Opts::new("ed_scan_level", "ED scan"), &["channel"]).unwrap(),
+            join_rssi: Gauge::new("join_rssi_dbm", "RSSI").unwrap(),
+            pan_info: GaugeVec::new(Opts::new("pan_info", "PAN"), &["channel", "pan_id", "addr"]).unwrap(),
+            initialize_duration: HistogramVec::new(HistogramOpts::new("initialize_duration_seconds", "Initialization").buckets(INITIALIZE_BUCKETS.to_vec()), &["step"]).unwrap(),
+        };
+        let mut started = Instant::now() - Duration::from_secs(3);
+        metrics.finish_step("active_scan", &mut started);
+        // the next step starts now
+        assert!(started.elapsed() < Duration::from_secs(1));
+        metrics.finish_step("join", &mut started);
+
+        let active_scan = metrics.initialize_duration.with_label_values(&["active_scan"]);
+        assert_eq!(active_scan.get_sample_count(), 1);
+        assert!((3.0..4.0).contains(&active_scan.get_sample_sum()));
+        let join = metrics.initialize_duration.with_label_values(&["join"]);
+        assert_eq!(join.get_sample_count(), 1);
+        assert!(join.get_sample_sum() < 1.0);
+    }
+
+    #[test]
+    fn test_period() {
+        assert_eq!(period(Task::Get(EpcLowVoltageSmartMeter::INSTANTANEOUS_ENERGY)), Some(Duration::from_secs(10)));
+        assert_eq!(period(Task::SyncFixedTime), Some(Duration::from_secs(INTERVAL_SECS as u64)));
+        assert_eq!(period(Task::Get(EpcLowVoltageSmartMeter::DAY_FOR_HISTORICAL_DATA_1)), None);
+    }
+
+    #[test]
+    fn test_decode_fixed_time_readings() {
+        let unit = EnergyUnit { kwh: 0.1 };
+        let r = response(Esv::GetRes, vec![(0xEA, b"\x07\xe7\x04\x0f\x0c\x1e\x00\x00\x00\x12\x34")]);
+        let readings = decode_fixed_time_readings(&r, unit).unwrap();
+        assert_eq!(readings.len(), 1);
+        assert_eq!(readings[0].direction, Direction::Normal);
+        assert_eq!(readings[0].at, DAY_START + 25 * INTERVAL_SECS);
+
+        // EB can not be read: nothing is recorded, so the sync is retried
+        let r = response(Esv::GetSna, vec![(0xEA, b"\x07\xe7\x04\x0f\x0c\x1e\x00\x00\x00\x12\x34"), (0xEB, b"")]);
+        assert!(decode_fixed_time_readings(&r, unit).is_err());
+        let r = response(Esv::GetRes, vec![(0xEA, b"\x07\xe7\x0d\x0f\x0c\x1e\x00\x00\x00\x12\x34")]);
+        assert!(decode_fixed_time_readings(&r, unit).is_err());
+    }
+
+    #[test]
+    fn test_decode_historical_readings() {
+        let unit = EnergyUnit { kwh: 0.1 };
+        let mut edt = vec![0x00, 0x01];
+        edt.extend((0..48u32).flat_map(|i| if i < 24 { i } else { 0xFFFFFFFE }.to_be_bytes()));
+
+        let kwh = decode_historical_readings(&response(Esv::GetRes, vec![(0xE2, &edt)]), 0xE2, 1, unit).unwrap();
+        assert_eq!(kwh.len(), 48);
+        assert_eq!(kwh[1], Some(0.1));
+        assert_eq!(kwh[24], None);
+
+        // not a sign the slots are lost
+        assert!(decode_historical_readings(&response(Esv::GetSna, vec![(0xE2, b"")]), 0xE2, 1, unit).is_err());
+        assert!(decode_historical_readings(&response(Esv::GetRes, vec![(0xE2, &edt)]), 0xE2, 2, unit).is_err());
+        assert!(decode_historical_readings(&response(Esv::GetRes, vec![]), 0xE2, 1, unit).is_err());
+    }
+}
@@ -0,0 +1,208 @@
+use std::collections::{BTreeMap, HashMap};
+
+use prometheus_exporter::prometheus::{
+    self,
+    core::{Collector, Desc},
+    proto::{MetricFamily, MetricType},
+    Gauge, GaugeVec, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, Opts,
+};
+
+// namespace and constant labels of everything exported
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Naming {
+    pub namespace: String,
+    pub const_labels: BTreeMap<String, String>,
+}
+
+impl Naming {
+    pub fn opts(&self, name: &str, help: &str) -> Opts {
+        Opts::new(name, help)
+            .namespace(self.namespace.clone())
+            .const_labels(self.const_labels.clone().into_iter().collect::<HashMap<_, _>>())
+    }
+
+    // the name as it is exported
+    pub fn fq_name(&self, name: &str) -> String {
+        if self.namespace.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}_{}", self.namespace, name)
+        }
+    }
+
+    pub fn counter(&self, name: &str, help: &str) -> IntCounter {
+        let counter = IntCounter::with_opts(self.opts(name, help)).expect("can not create counter");
+        prometheus::register(Box::new(counter.clone())).unwrap_or_else(|e| panic!("can not register counter {}: {}", name, e));
+        counter
+    }
+
+    pub fn counter_vec(&self, name: &str, help: &str, labels: &[&str]) -> IntCounterVec {
+        let counter = IntCounterVec::new(self.opts(name, help), labels).expect("can not create counter");
+        prometheus::register(Box::new(counter.clone())).unwrap_or_else(|e| panic!("can not register counter {}: {}", name, e));
+        counter
+    }
+
+    pub fn gauge(&self, name: &str, help: &str) -> Gauge {
+        let gauge = Gauge::with_opts(self.opts(name, help)).expect("can not create gauge");
+        prometheus::register(Box::new(gauge.clone())).unwrap_or_else(|e| panic!("can not register gauge {}: {}", name, e));
+        gauge
+    }
+
+    pub fn gauge_vec(&self, name: &str, help: &str, labels: &[&str]) -> GaugeVec {
+        let gauge = GaugeVec::new(self.opts(name, help), labels).expect("can not create gauge");
+        prometheus::register(Box::new(gauge.clone())).unwrap_or_else(|e| panic!("can not register gauge {}: {}", name, e));
+        gauge
+    }
+
+    pub fn histogram_vec(&self, name: &str, help: &str, labels: &[&str], buckets: Vec<f64>) -> HistogramVec {
+        let opts = HistogramOpts::from(self.opts(name, help)).buckets(buckets);
+        let histogram = HistogramVec::new(opts, labels).expect("can not create histogram");
+        prometheus::register(Box::new(histogram.clone())).unwrap_or_else(|e| panic!("can not register histogram {}: {}", name, e));
+        histogram
+    }
+}
+
+// a series of the names before they were namespaced, derived from a current metric
+struct Alias {
+    old: &'static str,
+    help: &'static str,
+    source: Box<dyn Collector>,
+    name: String,
+    // `None` if not renamed
+    desc: Option<Desc>,
+    // only the series with these labels, which are dropped
+    select: Vec<(&'static str, &'static str)>,
+    // summed over
+    drop: Vec<&'static str>,
+}
+
+// SMARTMETER_LEGACY_METRICS: the old names as gauges, for dashboards which have not migrated yet
+#[derive(Default)]
+pub struct LegacyMetrics {
+    aliases: Vec<Alias>,
+}
+
+impl LegacyMetrics {
+    pub fn alias(&mut self, old: &'static str, help: &'static str, source: &(impl Collector + Clone + 'static), name: String) -> &mut Self {
+        let desc = (old != name).then(|| Desc::new(old.to_string(), help.to_string(), vec![], HashMap::new()).expect("invalid legacy metric name"));
+        self.aliases.push(Alias {
+            old,
+            help,
+            source: Box::new(source.clone()),
+            name,
+            desc,
+            select: vec![],
+            drop: vec![],
+        });
+        self
+    }
+
+    pub fn register(self) {
+        prometheus::register(Box::new(self)).expect("can not register the legacy metrics");
+    }
+
+    // narrows the alias added last
+    pub fn select(&mut self, label: &'static str, value: &'static str) -> &mut Self {
+        if let Some(alias) = self.aliases.last_mut() {
+            alias.select.push((label, value));
+        }
+        self
+    }
+
+    pub fn sum_over(&mut self, label: &'static str) -> &mut Self {
+        if let Some(alias) = self.aliases.last_mut() {
+            alias.drop.push(label);
+        }
+        self
+    }
+}
+
+impl Collector for LegacyMetrics {
+    fn desc(&self) -> Vec<&Desc> {
+        self.aliases.iter().filter_map(|alias| alias.desc.as_ref()).collect()
+    }
+
+    fn collect(&self) -> Vec<MetricFamily> {
+        let mut mfs = vec![];
+        for alias in &self.aliases {
+            if alias.desc.is_none() {
+                continue;
+            }
+            let source = match alias.source.collect().into_iter().find(|mf| mf.get_name() == alias.name) {
+                Some(mf) => mf,
+                None => continue,
+            };
+            let metrics: Vec<(Vec<(&str, &str)>, f64)> = source.get_metric().iter()
+                .filter(|m| alias.select.iter().all(|&(name, value)| {
+                    m.get_label().iter().any(|label| label.get_name() == name && label.get_value() == value)
+                }))
+                .map(|m| {
+                    let labels = m.get_label().iter()
+                        .filter(|label| !alias.select.iter().any(|&(name, _)| label.get_name() == name) && !alias.drop.contains(&label.get_name()))
+                        .map(|label| (label.get_name(), label.get_value()))
+                        .collect();
+                    let value = match source.get_field_type() {
+                        MetricType::COUNTER => m.get_counter().get_value(),
+                        _ => m.get_gauge().get_value(),
+                    };
+                    (labels, value)
+                })
+                .collect();
+            let names: Vec<&str> = match metrics.first() {
+                Some((labels, _)) => labels.iter().map(|&(name, _)| name).collect(),
+                None => continue,
+            };
+            let vec = match GaugeVec::new(Opts::new(alias.old, alias.help), &names) {
+                Ok(vec) => vec,
+                Err(_) => continue,
+            };
+            for (labels, value) in metrics {
+                let values: Vec<&str> = labels.iter().map(|&(_, value)| value).collect();
+                vec.with_label_values(&values).add(value);
+            }
+            mfs.extend(vec.collect());
+        }
+        mfs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_naming() {
+        let naming = Naming {
+            namespace: "smartmeter".to_string(),
+            const_labels: BTreeMap::from([("site".to_string(), "home".to_string())]),
+        };
+        assert_eq!(naming.fq_name("power_watts"), "smartmeter_power_watts");
+        let gauge = Gauge::with_opts(naming.opts("power_watts", "Power in Watt")).unwrap();
+        let mfs = gauge.collect();
+        assert_eq!(mfs[0].get_name(), "smartmeter_power_watts");
+        assert_eq!(mfs[0].get_metric()[0].get_label()[0].get_name(), "site");
+        assert_eq!(Naming::default().fq_name("power_watts"), "power_watts");
+    }
+
+    #[test]
+    fn test_legacy() {
+        let requests = IntCounterVec::new(Opts::new("smartmeter_requests_total", "Requests"), &["result"]).unwrap();
+        requests.with_label_values(&["success"]).inc_by(3);
+        requests.with_label_values(&["error"]).inc();
+
+        let mut legacy = LegacyMetrics::default();
+        legacy.alias("counter_request_energy", "# of times client send energy request", &requests, "smartmeter_requests_total".to_string())
+            .sum_over("result");
+        legacy.alias("counter_error_sksendto", "# of error when sending data to sensor", &requests, "smartmeter_requests_total".to_string())
+            .select("result", "error");
+
+        let mfs = legacy.collect();
+        assert_eq!(mfs.len(), 2);
+        assert_eq!(mfs[0].get_name(), "counter_request_energy");
+        assert_eq!(mfs[0].get_field_type(), MetricType::GAUGE);
+        assert!(mfs[0].get_metric()[0].get_label().is_empty());
+        assert_eq!(mfs[0].get_metric()[0].get_gauge().get_value(), 4.0);
+        assert_eq!(mfs[1].get_name(), "counter_error_sksendto");
+        assert_eq!(mfs[1].get_metric()[0].get_gauge().get_value(), 1.0);
+    }
+}
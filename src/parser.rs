@@ -3,15 +3,19 @@
 use std::fmt;
 
 use bytes::Bytes;
-use nom::{IResult, bytes::streaming::{tag, take_while1, take, take_while_m_n }, branch::alt, character::{streaming::{space1, hex_digit1, crlf}, is_alphanumeric, is_hex_digit}, sequence::{tuple, delimited, preceded}, combinator::{map_res, map, opt, all_consuming, recognize}, ToUsize, number::streaming::{be_u8, be_u16}, multi::count };
+use nom::{IResult, bytes::streaming::{tag, take_while1, take, take_while_m_n }, branch::alt, character::{streaming::{space1, hex_digit1, digit1, crlf}, is_alphanumeric, is_hex_digit}, sequence::{tuple, delimited, preceded}, combinator::{map_res, map, opt, all_consuming, recognize}, ToUsize, number::streaming::{be_u8, be_u16}, multi::{count, many_till, many1} };
 
-use crate::echonet_lite::{EchonetLite, EData, EDataFormat1, Eoj, EDataProperty, EHd};
+use crate::dialect::Dialect;
+use crate::echonet_lite::{EchonetLite, EHD1_ECHONET_LITE, EHD2_FORMAT1, EHD2_FORMAT2, EData, EDataFormat1, Eoj, EDataProperty, EHd, Esv};
 
 pub type Addr64 = String;
 pub type IpAddr = String;
 #[derive(PartialEq)]
 pub enum Response {
+    // result of a command when echo back is off (SFE 0)
     Ok,
+    // result of SKLL64 when echo back is off
+    Ipv6Addr(IpAddr),
 
     // echo backs
     SkReset,
@@ -38,34 +42,293 @@ pub enum Response {
     SkJoin {
         ipaddr: IpAddr,
     },
+    // SKVER, also without echo back
+    EVer {
+        version: String,
+    },
+    // SKAPPVER
+    EAppVer {
+        version: String,
+    },
+    // SKINFO: own addresses and the PAN joined
+    EInfo {
+        ipaddr: IpAddr,
+        addr64: Addr64,
+        channel: u8,
+        pan_id: u16,
+        addr16: u16,
+    },
+    // SKTABLE 1: own IP addresses
+    EAddr(Vec<IpAddr>),
+    // SKTABLE 2: neighbor cache
+    ENeighbor(Vec<Neighbor>),
+    // register readback (SKSREG without value)
+    ESReg {
+        val: u32,
+    },
+    // ROPT, also without echo back ("OK 01")
+    ROpt {
+        mode: u8,
+    },
+    WOpt {
+        mode: u8,
+    },
     SkSendTo {
         handle: u8,
         ipaddr: IpAddr,
         port: u16,
         sec: u8,
         datalen: u16,
-        result: u8, // param of Event 0x21
+        result: SendResult, // param of Event 0x21
+    },
+
+    // the module rejected a command. `command` is the echo back, if any.
+    Fail {
+        command: Option<String>,
+        code: FailCode,
     },
 
     // events
     Event {
-        num: u8,
+        event: Event,
         sender: IpAddr,
-        param: Option<u8>,
     },
     EPanDesc(PanDesc),
+    // result of an energy detection scan (SKSCAN 0)
+    EEdScan(Vec<ChannelEnergy>),
     ERxUdp {
         sender: IpAddr,
         dest: IpAddr,
         rport: u16,
         lport: u16,
         senderlla: Addr64,
+        // BP35C0 only
+        rssi: Option<i8>,
         secured: u8,
         datalen: u16,
-        data: EchonetLite,
+        data: UdpPayload,
+    }
+}
+
+impl Response {
+    // the command this echo back or result is the answer of, see `Command::name`.
+    // `None` for OK and FAIL, which answer any command, and for events
+    pub fn command(&self) -> Option<&'static str> {
+        match self {
+            Response::Ipv6Addr(_) | Response::SkLl64 { .. } => Some("SKLL64"),
+            Response::SkReset => Some("SKRESET"),
+            Response::SkSetRbid { .. } => Some("SKSETRBID"),
+            Response::SkSetPwd { .. } => Some("SKSETPWD"),
+            Response::SkScan { .. } => Some("SKSCAN"),
+            Response::SkSreg { .. } | Response::ESReg { .. } => Some("SKSREG"),
+            Response::SkJoin { .. } => Some("SKJOIN"),
+            Response::EVer { .. } => Some("SKVER"),
+            Response::EAppVer { .. } => Some("SKAPPVER"),
+            Response::EInfo { .. } => Some("SKINFO"),
+            Response::EAddr(_) | Response::ENeighbor(_) => Some("SKTABLE"),
+            Response::ROpt { .. } => Some("ROPT"),
+            Response::WOpt { .. } => Some("WOPT"),
+            Response::SkSendTo { .. } => Some("SKSENDTO"),
+            Response::Ok | Response::Fail { .. } => None,
+            Response::Event { .. } | Response::EPanDesc(_) | Response::EEdScan(_) | Response::ERxUdp { .. } => None,
+        }
+    }
+}
+
+// FAIL ERxx
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum FailCode {
+    UnsupportedCommand,         // ER04
+    InvalidNumberOfParameters,  // ER05
+    ParameterOutOfRange,        // ER06
+    UartInputError,             // ER09
+    ExecutionFailed,            // ER10
+    Other(u8),
+}
+
+impl From<u8> for FailCode {
+    fn from(code: u8) -> Self {
+        match code {
+            4 => FailCode::UnsupportedCommand,
+            5 => FailCode::InvalidNumberOfParameters,
+            6 => FailCode::ParameterOutOfRange,
+            9 => FailCode::UartInputError,
+            10 => FailCode::ExecutionFailed,
+            code => FailCode::Other(code),
+        }
+    }
+}
+
+impl From<FailCode> for u8 {
+    fn from(code: FailCode) -> Self {
+        match code {
+            FailCode::UnsupportedCommand => 4,
+            FailCode::InvalidNumberOfParameters => 5,
+            FailCode::ParameterOutOfRange => 6,
+            FailCode::UartInputError => 9,
+            FailCode::ExecutionFailed => 10,
+            FailCode::Other(code) => code,
+        }
+    }
+}
+
+impl FailCode {
+    // as the module prints it, e.g. "ER04"
+    pub fn label(&self) -> String {
+        format!("ER{:02}", u8::from(*self))
+    }
+}
+
+impl fmt::Display for FailCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let meaning = match self {
+            FailCode::UnsupportedCommand => "unsupported command",
+            FailCode::InvalidNumberOfParameters => "invalid number of parameters",
+            FailCode::ParameterOutOfRange => "parameter out of range",
+            FailCode::UartInputError => "UART input error",
+            FailCode::ExecutionFailed => "command failed to execute",
+            FailCode::Other(_) => "unknown error",
+        };
+        write!(f, "{} ({})", self.label(), meaning)
+    }
+}
+
+// param of EVENT 21
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SendResult {
+    Success,
+    Failure,
+    // the address of the destination is being resolved
+    NeighborSolicitation,
+    Unknown(u8),
+}
+
+impl From<u8> for SendResult {
+    fn from(param: u8) -> Self {
+        match param {
+            0x00 => SendResult::Success,
+            0x01 => SendResult::Failure,
+            0x02 => SendResult::NeighborSolicitation,
+            param => SendResult::Unknown(param),
+        }
+    }
+}
+
+// EVENT of SKSTACK IP
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Event {
+    NsReceived,                         // 0x01
+    NaReceived,                         // 0x02
+    EchoRequestReceived,                // 0x05
+    EdScanDone,                         // 0x1F
+    BeaconReceived,                     // 0x20
+    UdpSent(SendResult),                // 0x21
+    ActiveScanDone,                     // 0x22
+    PanaConnectFailed,                  // 0x24
+    PanaConnected,                      // 0x25
+    PanaSessionTerminationRequested,    // 0x26
+    PanaSessionTerminated,              // 0x27
+    PanaSessionTerminationTimedOut,     // 0x28
+    PanaSessionExpired,                 // 0x29
+    TransmissionLimited,                // 0x32
+    TransmissionLimitReleased,          // 0x33
+    Unknown {
+        num: u8,
+        param: Option<u8>,
+    },
+}
+
+impl Event {
+    pub fn new(num: u8, param: Option<u8>) -> Event {
+        match (num, param) {
+            (0x01, _) => Event::NsReceived,
+            (0x02, _) => Event::NaReceived,
+            (0x05, _) => Event::EchoRequestReceived,
+            (0x1F, _) => Event::EdScanDone,
+            (0x20, _) => Event::BeaconReceived,
+            (0x21, Some(result)) => Event::UdpSent(SendResult::from(result)),
+            (0x22, _) => Event::ActiveScanDone,
+            (0x24, _) => Event::PanaConnectFailed,
+            (0x25, _) => Event::PanaConnected,
+            (0x26, _) => Event::PanaSessionTerminationRequested,
+            (0x27, _) => Event::PanaSessionTerminated,
+            (0x28, _) => Event::PanaSessionTerminationTimedOut,
+            (0x29, _) => Event::PanaSessionExpired,
+            (0x32, _) => Event::TransmissionLimited,
+            (0x33, _) => Event::TransmissionLimitReleased,
+            (num, param) => Event::Unknown { num, param },
+        }
+    }
+
+    // label to count events by
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Event::NsReceived => "ns_received",
+            Event::NaReceived => "na_received",
+            Event::EchoRequestReceived => "echo_request_received",
+            Event::EdScanDone => "ed_scan_done",
+            Event::BeaconReceived => "beacon_received",
+            Event::UdpSent(_) => "udp_sent",
+            Event::ActiveScanDone => "active_scan_done",
+            Event::PanaConnectFailed => "pana_connect_failed",
+            Event::PanaConnected => "pana_connected",
+            Event::PanaSessionTerminationRequested => "pana_session_termination_requested",
+            Event::PanaSessionTerminated => "pana_session_terminated",
+            Event::PanaSessionTerminationTimedOut => "pana_session_termination_timed_out",
+            Event::PanaSessionExpired => "pana_session_expired",
+            Event::TransmissionLimited => "transmission_limited",
+            Event::TransmissionLimitReleased => "transmission_limit_released",
+            Event::Unknown { .. } => "unknown",
+        }
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Event::NsReceived => write!(f, "received a neighbor solicitation"),
+            Event::NaReceived => write!(f, "received a neighbor advertisement"),
+            Event::EchoRequestReceived => write!(f, "received an echo request"),
+            Event::EdScanDone => write!(f, "energy detection scan finished"),
+            Event::BeaconReceived => write!(f, "received a beacon"),
+            Event::UdpSent(result) => write!(f, "UDP sent: {:?}", result),
+            Event::ActiveScanDone => write!(f, "active scan finished"),
+            Event::PanaConnectFailed => write!(f, "PANA authentication failed"),
+            Event::PanaConnected => write!(f, "PANA authentication succeeded"),
+            Event::PanaSessionTerminationRequested => write!(f, "the peer requested to terminate the PANA session"),
+            Event::PanaSessionTerminated => write!(f, "PANA session terminated"),
+            Event::PanaSessionTerminationTimedOut => write!(f, "no response to the termination request of the PANA session"),
+            Event::PanaSessionExpired => write!(f, "PANA session expired"),
+            Event::TransmissionLimited => write!(f, "transmission time limit reached, sending is suspended"),
+            Event::TransmissionLimitReleased => write!(f, "transmission time limit released"),
+            Event::Unknown { num, param } => write!(f, "unknown event {:#x} ({:?})", num, param),
+        }
     }
 }
 
+// energy detected on a channel, in the unit of LQI
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct ChannelEnergy {
+    pub channel: u8,
+    pub level: u8,
+}
+
+// entry of the neighbor cache
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Neighbor {
+    pub ipaddr: IpAddr,
+    pub addr64: Addr64,
+    pub addr16: u16,
+}
+
+// payload of ERXUDP
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum UdpPayload {
+    EchonetLite(EchonetLite),
+    // not ECHONET Lite (e.g. PANA), or too short or malformed to decode
+    Other(Bytes),
+}
+
 #[derive(PartialEq, Default, Clone)]
 pub struct PanDesc {
     pub channel: u8,
@@ -73,9 +336,21 @@ pub struct PanDesc {
     pub pan_id: u16,
     pub addr: String, // Addr64
     pub lqi: u8,
+    pub rssi: Option<i8>, // BP35C0 only
     pub pair_id: String, // char[8]
 }
 
+// RSSI in dBm from LQI, as the manual of the module defines it
+pub fn lqi_to_dbm(lqi: u8) -> f64 {
+    0.275 * lqi as f64 - 104.27
+}
+
+impl PanDesc {
+    // measured by BP35C0, converted from LQI otherwise
+    pub fn rssi_dbm(&self) -> f64 {
+        self.rssi.map(f64::from).unwrap_or_else(|| lqi_to_dbm(self.lqi))
+    }
+}
 
 impl fmt::Debug for PanDesc {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -85,6 +360,7 @@ impl fmt::Debug for PanDesc {
          .field("pan_id", &format_args!("{:#x}", self.pan_id))
          .field("addr", &self.addr)
          .field("lqi", &format_args!("{:#x}", self.lqi))
+         .field("rssi", &self.rssi)
          .field("pair_id", &self.pair_id)
          .finish()
     }
@@ -98,6 +374,11 @@ impl fmt::Debug for Response {
                 f.debug_struct("Ok")
                  .finish()
             },
+            Response::Ipv6Addr(ipaddr) => {
+                f.debug_tuple("Ipv6Addr")
+                 .field(&ipaddr)
+                 .finish()
+            },
             Response::SkReset {
             } => {
                 f.debug_struct("SkReset")
@@ -155,6 +436,66 @@ impl fmt::Debug for Response {
                  .field("ipaddr", &ipaddr)
                  .finish()
             },
+            Response::EVer {
+                version,
+            } => {
+                f.debug_struct("EVer")
+                 .field("version", &version)
+                 .finish()
+            },
+            Response::EAppVer {
+                version,
+            } => {
+                f.debug_struct("EAppVer")
+                 .field("version", &version)
+                 .finish()
+            },
+            Response::EInfo {
+                ipaddr,
+                addr64,
+                channel,
+                pan_id,
+                addr16,
+            } => {
+                f.debug_struct("EInfo")
+                 .field("ipaddr", &ipaddr)
+                 .field("addr64", &addr64)
+                 .field("channel", &format_args!("{:#x}", channel))
+                 .field("pan_id", &format_args!("{:#x}", pan_id))
+                 .field("addr16", &format_args!("{:#x}", addr16))
+                 .finish()
+            },
+            Response::EAddr(addrs) => {
+                f.debug_tuple("EAddr")
+                 .field(&addrs)
+                 .finish()
+            },
+            Response::ENeighbor(neighbors) => {
+                f.debug_tuple("ENeighbor")
+                 .field(&neighbors)
+                 .finish()
+            },
+            Response::ESReg {
+                val,
+            } => {
+                f.debug_struct("ESReg")
+                 .field("val", &format_args!("{:#x}", val))
+                 .finish()
+            },
+            Response::ROpt {
+                mode,
+            } => {
+                f.debug_struct("ROpt")
+                 .field("mode", &format_args!("{:#x}", mode))
+                 .finish()
+            },
+            Response::WOpt {
+                mode,
+            } => {
+                f.debug_struct("WOpt")
+                 .field("mode", &format_args!("{:#x}", mode))
+                 .finish()
+            },
             Response::SkSendTo {
                 handle,
                 ipaddr,
@@ -169,18 +510,25 @@ impl fmt::Debug for Response {
                  .field("port", &port)
                  .field("sec", &format_args!("{:#x}", sec))
                  .field("datalen", &datalen)
-                 .field("result", &format_args!("{:#x}", result))
+                 .field("result", &result)
+                 .finish()
+            },
+            Response::Fail {
+                command,
+                code,
+            } => {
+                f.debug_struct("Fail")
+                 .field("command", &command)
+                 .field("code", &code)
                  .finish()
             },
             Response::Event {
-                num,
+                event,
                 sender,
-                param,
             } => {
                 f.debug_struct("Event")
-                 .field("num", &format_args!("{:#x}", num))
+                 .field("event", &event)
                  .field("sender", &sender)
-                 .field("param", &param)
                  .finish()
             },
             Response::EPanDesc(pan_desc) => {
@@ -188,12 +536,18 @@ impl fmt::Debug for Response {
                  .field("pan_desc", &pan_desc)
                  .finish()
             },
+            Response::EEdScan(channels) => {
+                f.debug_tuple("EEdScan")
+                 .field(&channels)
+                 .finish()
+            },
             Response::ERxUdp {
                 sender,
                 dest,
                 rport,
                 lport,
                 senderlla,
+                rssi,
                 secured,
                 datalen,
                 data,
@@ -204,6 +558,7 @@ impl fmt::Debug for Response {
                  .field("rport", &rport)
                  .field("lport", &lport)
                  .field("senderlla", &senderlla)
+                 .field("rssi", &rssi)
                  .field("secured", &format_args!("{:#x}", secured))
                  .field("datalen", &datalen)
                  .field("data", &data)
@@ -218,9 +573,44 @@ fn parse_ok(input: &[u8]) -> IResult<&[u8], Response> {
     Ok((input, Response::Ok))
 }
 
+fn parse_fail(input: &[u8]) -> IResult<&[u8], FailCode> {
+    let (input, (_, _, code, _)) = tuple((
+        tag("FAIL"),
+        space1,
+        preceded(tag("ER"), map_res(digit1, from_dec_u8)),
+        crlf,
+    ))(input)?;
+
+    Ok((input, FailCode::from(code)))
+}
+
+// FAIL without an echo back
+fn parse_bare_fail(input: &[u8]) -> IResult<&[u8], Response> {
+    let (input, code) = parse_fail(input)?;
+    Ok((input, Response::Fail {
+        command: None,
+        code,
+    }))
+}
+
+// the result of a command: OK, or FAIL ERxx which is reported with the name of the command.
+// Only the name is kept, the echo back may contain the password.
+fn parse_result<'a>(input: &'a [u8], command: &str) -> IResult<&'a [u8], Option<Response>> {
+    alt((
+        map(parse_ok, |_| None),
+        map(parse_fail, |code| Some(Response::Fail {
+            command: Some(command.to_string()),
+            code,
+        })),
+    ))(input)
+}
+
 fn parse_skreset(input: &[u8]) -> IResult<&[u8], Response> {
     let (input, _) = tuple((tag("SKRESET"), crlf))(input)?;
-    let (input, _) = parse_ok(input)?;
+    let (input, fail) = parse_result(input, "SKRESET")?;
+    if let Some(fail) = fail {
+        return Ok((input, fail));
+    }
 
     Ok((input, Response::SkReset))
 }
@@ -232,7 +622,10 @@ fn parse_sksetrbid(input: &[u8]) -> IResult<&[u8], Response> {
         take_while1(is_alphanumeric),
         crlf,
     ))(input)?;
-    let (input, _) = parse_ok(input)?;
+    let (input, fail) = parse_result(input, "SKSETRBID")?;
+    if let Some(fail) = fail {
+        return Ok((input, fail));
+    }
 
     let id = std::str::from_utf8(id).map_err(|e|
         nom::Err::Failure(nom::error::Error::new(input, nom::error::ErrorKind::AlphaNumeric))
@@ -248,6 +641,11 @@ fn from_hex_u8(input: &[u8]) -> Result<u8, std::num::ParseIntError> {
     u8::from_str_radix(&str, 16)
 }
 
+fn from_dec_u8(input: &[u8]) -> Result<u8, std::num::ParseIntError> {
+    let str = String::from_utf8_lossy(input);
+    str.parse()
+}
+
 fn from_hex_u16(input: &[u8]) -> Result<u16, std::num::ParseIntError> {
     let str = String::from_utf8_lossy(input);
     u16::from_str_radix(&str, 16)
@@ -271,7 +669,10 @@ fn parse_sksetpwd(input: &[u8]) -> IResult<&[u8], Response> {
         take_while1(is_alphanumeric),
         crlf,
     ))(input)?;
-    let (input, _) = parse_ok(input)?;
+    let (input, fail) = parse_result(input, "SKSETPWD")?;
+    if let Some(fail) = fail {
+        return Ok((input, fail));
+    }
 
     if pwd.len() != len.to_usize() {
         return Err(nom::Err::Failure(nom::error::Error::new(input, nom::error::ErrorKind::Verify)));
@@ -288,7 +689,7 @@ fn parse_sksetpwd(input: &[u8]) -> IResult<&[u8], Response> {
 }
 
 fn parse_skscan(input: &[u8]) -> IResult<&[u8], Response> {
-    let (input, (_, _, mode, _, channel_mask, _, duration, _)) = tuple((
+    let (input, (_, _, mode, _, channel_mask, _, duration, _, _)) = tuple((
         tag("SKSCAN"),
         space1,
         map_res(hex_digit1, from_hex_u8),
@@ -296,9 +697,14 @@ fn parse_skscan(input: &[u8]) -> IResult<&[u8], Response> {
         map_res(hex_digit1, from_hex_u32),
         space1,
         map_res(hex_digit1, from_hex_u8),
+        // side of BP35C0
+        opt(preceded(space1, hex_digit1)),
         crlf,
     ))(input)?;
-    let (input, _) = parse_ok(input)?;
+    let (input, fail) = parse_result(input, "SKSCAN")?;
+    if let Some(fail) = fail {
+        return Ok((input, fail));
+    }
 
     Ok((input, Response::SkScan {
         mode,
@@ -335,34 +741,45 @@ fn parse_ipv6_addr(input: &[u8]) -> IResult<&[u8], IpAddr> {
     Ok((input, addr.to_string()))
 }
 
-fn parse_event(input: &[u8]) -> IResult<&[u8], Response> {
-    let (input, (_, _, num, _, sender, _, param, _)) = tuple((
+fn parse_event(input: &[u8], dialect: Dialect) -> IResult<&[u8], Response> {
+    let (input, (_, _, num, _, sender)) = tuple((
         tag("EVENT"),
         space1,
         map_res(hex_digit1, from_hex_u8),
         space1,
         parse_ipv6_addr,
+    ))(input)?;
+    // BP35C0: side before the param
+    let (input, _) = match dialect {
+        Dialect::Bp35a1 => (input, None),
+        Dialect::Bp35c0 => map(tuple((space1, hex_digit1)), Some)(input)?,
+    };
+    let (input, (_, param, _)) = tuple((
         opt(space1),
         opt(map_res(hex_digit1, from_hex_u8)),
         crlf,
     ))(input)?;
 
     Ok((input, Response::Event {
-        num,
+        event: Event::new(num, param),
         sender,
-        param,
     }))
 }
-fn parse_epandesc(input: &[u8]) -> IResult<&[u8], Response> {
-    let (input, (_, channel, channel_page, pan_id, addr, lqi, pair_id)) = tuple((
+fn parse_epandesc(input: &[u8], dialect: Dialect) -> IResult<&[u8], Response> {
+    let (input, (_, channel, channel_page, pan_id, addr, lqi)) = tuple((
         tuple((tag("EPANDESC"), crlf)),
         delimited(tag("  Channel:"), map_res(hex_digit1, from_hex_u8), crlf),
         delimited(tag("  Channel Page:"), map_res(hex_digit1, from_hex_u8), crlf),
         delimited(tag("  Pan ID:"), map_res(hex_digit1, from_hex_u16), crlf),
         delimited(tag("  Addr:"), take_while1(is_alphanumeric), crlf),
         delimited(tag("  LQI:"), map_res(hex_digit1, from_hex_u8), crlf),
-        delimited(tag("  PairID:"), take_while1(is_alphanumeric), crlf),
     ))(input)?;
+    // BP35C0 reports the RSSI in some modes
+    let (input, rssi) = match dialect {
+        Dialect::Bp35a1 => (input, None),
+        Dialect::Bp35c0 => opt(delimited(tag("  RSSI:"), parse_rssi, crlf))(input)?,
+    };
+    let (input, pair_id) = delimited(tag("  PairID:"), take_while1(is_alphanumeric), crlf)(input)?;
 
     let addr = std::str::from_utf8(addr).map_err(|e|
         nom::Err::Failure(nom::error::Error::new(input, nom::error::ErrorKind::AlphaNumeric))
@@ -377,10 +794,30 @@ fn parse_epandesc(input: &[u8]) -> IResult<&[u8], Response> {
         pan_id,
         addr: addr.to_string(),
         lqi,
+        rssi,
         pair_id: pair_id.to_string(),
     })))
 }
 
+// pairs of channel and level on one line
+fn parse_eedscan(input: &[u8]) -> IResult<&[u8], Response> {
+    let (input, (_, channels, _)) = tuple((
+        tuple((tag("EEDSCAN"), crlf)),
+        many1(map(
+            tuple((map_res(hex_digit1, from_hex_u8), space1, map_res(hex_digit1, from_hex_u8), opt(space1))),
+            |(channel, _, level, _)| ChannelEnergy { channel, level },
+        )),
+        crlf,
+    ))(input)?;
+
+    Ok((input, Response::EEdScan(channels)))
+}
+
+// dBm in a two's complement byte
+fn parse_rssi(input: &[u8]) -> IResult<&[u8], i8> {
+    map(map_res(hex_digit1, from_hex_u8), |rssi| rssi as i8)(input)
+}
+
 fn parse_sksreg(input: &[u8]) -> IResult<&[u8], Response> {
     let (input, (_, _, sreg, _, val, _)) = tuple((
         tag("SKSREG"),
@@ -390,7 +827,10 @@ fn parse_sksreg(input: &[u8]) -> IResult<&[u8], Response> {
         map_res(hex_digit1, from_hex_u32),
         crlf,
     ))(input)?;
-    let (input, _) = parse_ok(input)?;
+    let (input, fail) = parse_result(input, "SKSREG")?;
+    if let Some(fail) = fail {
+        return Ok((input, fail));
+    }
 
     Ok((input, Response::SkSreg {
         sreg,
@@ -428,15 +868,18 @@ fn parse_skjoin(input: &[u8]) -> IResult<&[u8], Response> {
         parse_ipv6_addr,
         crlf,
     ))(input)?;
-    let (input, _) = parse_ok(input)?;
+    let (input, fail) = parse_result(input, "SKJOIN")?;
+    if let Some(fail) = fail {
+        return Ok((input, fail));
+    }
 
     Ok((input, Response::SkJoin {
         ipaddr,
     }))
 }
 
-fn parse_erxudp(input: &[u8]) -> IResult<&[u8], Response> {
-    let (input, (_, _, sender, _, dest, _, rport, _, lport, _, senderlla, _, secured, _, datalen, _)) = tuple((
+fn parse_erxudp(input: &[u8], dialect: Dialect) -> IResult<&[u8], Response> {
+    let (input, (_, _, sender, _, dest, _, rport, _, lport, _, senderlla, _)) = tuple((
         tag("ERXUDP"),
         space1,
         parse_ipv6_addr,
@@ -449,28 +892,31 @@ fn parse_erxudp(input: &[u8]) -> IResult<&[u8], Response> {
         space1,
         take_while1(is_alphanumeric),
         space1,
-        map_res(hex_digit1, from_hex_u8),
-        space1,
-        map_res(hex_digit1, from_hex_u16),
-        space1,
     ))(input)?;
+    // BP35C0: SIDE after SECURED, and RSSI before it in some modes.
+    // Without RSSI, the first field of the data is not a hex number followed by a space
+    let (input, (rssi, secured, datalen)) = match dialect {
+        Dialect::Bp35a1 => map(
+            tuple((map_res(hex_digit1, from_hex_u8), space1, map_res(hex_digit1, from_hex_u16), space1)),
+            |(secured, _, datalen, _)| (None, secured, datalen),
+        )(input)?,
+        Dialect::Bp35c0 => alt((
+            map(
+                tuple((parse_rssi, space1, map_res(hex_digit1, from_hex_u8), space1, hex_digit1, space1, map_res(hex_digit1, from_hex_u16), space1)),
+                |(rssi, _, secured, _, _, _, datalen, _)| (Some(rssi), secured, datalen),
+            ),
+            map(
+                tuple((map_res(hex_digit1, from_hex_u8), space1, hex_digit1, space1, map_res(hex_digit1, from_hex_u16), space1)),
+                |(secured, _, _, _, datalen, _)| (None, secured, datalen),
+            ),
+        ))(input)?,
+    };
 
     let addr = std::str::from_utf8(senderlla).map_err(|_e|
         nom::Err::Failure(nom::error::Error::new(input, nom::error::ErrorKind::AlphaNumeric))
     )?;
 
-    let (input, data) = take(datalen)(input)?;
-    let (data, ehd) = parse_ehd(data)?;
-
-    let edata = if ehd.ehd1 == 0x10 && ehd.ehd2 == 0x81 {
-        let (_, edata) = all_consuming(parse_edata)(data)?;
-
-        edata
-    } else {
-        let bytes = Bytes::copy_from_slice(data);
-        EData::InvalidEData(bytes)
-    };
-    let (input, _) = crlf(input)?;
+    let (input, data) = parse_erxudp_data(input, datalen)?;
 
     Ok((input, Response::ERxUdp {
         sender,
@@ -478,15 +924,50 @@ fn parse_erxudp(input: &[u8]) -> IResult<&[u8], Response> {
         rport,
         lport,
         senderlla: addr.to_string(),
+        rssi,
         secured,
         datalen,
-        data: EchonetLite {
-            ehd,
-            edata
-        }
+        data: parse_udp_payload(&data),
     }))
 }
 
+// the payload is `datalen` raw bytes, or 2 * `datalen` hex digits when the module is set to ASCII (WOPT 01).
+// At `datalen` bytes into a hex payload there is a digit, never CRLF, so the raw form can not match it by mistake.
+fn parse_erxudp_data(input: &[u8], datalen: u16) -> IResult<&[u8], Bytes> {
+    alt((
+        map(tuple((take(datalen), crlf)), |(data, _)| Bytes::copy_from_slice(data)),
+        map(tuple((map_res(take_while_m_n(datalen as usize * 2, datalen as usize * 2, is_hex_digit), from_hex_bytes), crlf)), |(data, _)| data),
+    ))(input)
+}
+
+fn from_hex_bytes(input: &[u8]) -> Result<Bytes, std::num::ParseIntError> {
+    input.chunks(2).map(from_hex_u8).collect::<Result<Vec<u8>, _>>().map(Bytes::from)
+}
+
+// decode the payload as ECHONET Lite if possible. Anything else is kept as is, it must not stop the reader.
+fn parse_udp_payload(data: &[u8]) -> UdpPayload {
+    let frame = match parse_ehd(data) {
+        Ok((edata, ehd)) if ehd.ehd1 == EHD1_ECHONET_LITE && ehd.ehd2 == EHD2_FORMAT1 => {
+            all_consuming(parse_edata)(edata).ok().map(|(_, edata)| EchonetLite {
+                ehd,
+                edata,
+            })
+        },
+        Ok((edata, ehd)) if ehd.ehd1 == EHD1_ECHONET_LITE && ehd.ehd2 == EHD2_FORMAT2 => {
+            Some(EchonetLite {
+                ehd,
+                edata: EData::EDataFormat2(Bytes::copy_from_slice(edata)),
+            })
+        },
+        _ => None,
+    };
+
+    match frame {
+        Some(frame) => UdpPayload::EchonetLite(frame),
+        None => UdpPayload::Other(Bytes::copy_from_slice(data)),
+    }
+}
+
 fn parse_ehd(input: &[u8]) -> IResult<&[u8], EHd> {
     let (input, (ehd1, ehd2, tid)) = tuple((
         be_u8,
@@ -545,14 +1026,14 @@ fn parse_edata(input: &[u8]) -> IResult<&[u8], EData> {
     Ok((input, EData::EDataFormat1(EDataFormat1 {
         seoj,
         deoj,
-        esv,
+        esv: Esv::from(esv),
         opc,
         props,
     })))
 }
 
-fn parse_sksendto(input: &[u8]) -> IResult<&[u8], Response> {
-    let (input, (_, _, handle, _, ipaddr, _, port, _, sec, _, datalen, _, _)) = tuple((
+fn parse_sksendto(input: &[u8], dialect: Dialect) -> IResult<&[u8], Response> {
+    let (input, (_, _, handle, _, ipaddr, _, port, _, sec, _)) = tuple((
         tag("SKSENDTO"),
         space1,
         map_res(hex_digit1, from_hex_u8),
@@ -563,17 +1044,35 @@ fn parse_sksendto(input: &[u8]) -> IResult<&[u8], Response> {
         space1,
         map_res(hex_digit1, from_hex_u8),
         space1,
+    ))(input)?;
+    // side of BP35C0
+    let (input, _) = match dialect {
+        Dialect::Bp35a1 => (input, None),
+        Dialect::Bp35c0 => map(tuple((hex_digit1, space1)), Some)(input)?,
+    };
+    let (input, (datalen, _, _)) = tuple((
         map_res(hex_digit1, from_hex_u16),
         space1,
         crlf,
     ))(input)?;
 
-    let (input, event) = parse_event(input)?;
-    let (input, _) = parse_ok(input)?;
-    let (input, _) = crlf(input)?;
+    // a rejected SKSENDTO is not followed by EVENT 21
+    let (input, event) = alt((
+        map(parse_fail, Err),
+        map(tuple((|i| parse_event(i, dialect), parse_ok, crlf)), |(event, _, _)| Ok(event)),
+    ))(input)?;
+    let event = match event {
+        Ok(event) => event,
+        Err(code) => {
+            return Ok((input, Response::Fail {
+                command: Some("SKSENDTO".to_string()),
+                code,
+            }));
+        }
+    };
 
 
-    if let Response::Event { num: 0x21, param: Some(result), .. } = event {
+    if let Response::Event { event: Event::UdpSent(result), .. } = event {
         Ok((input, Response::SkSendTo {
             handle,
             ipaddr,
@@ -587,19 +1086,231 @@ fn parse_sksendto(input: &[u8]) -> IResult<&[u8], Response> {
     }
 }
 
-pub fn parser(input: &[u8]) -> IResult<&[u8], Response> {
+// the result of a query following its echo back: what `result` parses, or FAIL
+fn parse_query_result<'a>(input: &'a [u8], command: &str, result: fn(&[u8]) -> IResult<&[u8], Response>) -> IResult<&'a [u8], Response> {
+    alt((
+        result,
+        map(parse_fail, |code| Response::Fail {
+            command: Some(command.to_string()),
+            code,
+        }),
+    ))(input)
+}
+
+fn parse_skver(input: &[u8]) -> IResult<&[u8], Response> {
+    let (input, _) = tuple((tag("SKVER"), crlf))(input)?;
+    parse_query_result(input, "SKVER", parse_ever)
+}
+
+// SKVER without echo back
+fn parse_ever(input: &[u8]) -> IResult<&[u8], Response> {
+    let (input, (_, _, version, _, _)) = tuple((
+        tag("EVER"),
+        space1,
+        take_while1(|c: u8| c.is_ascii_graphic()),
+        crlf,
+        parse_ok,
+    ))(input)?;
+
+    Ok((input, Response::EVer {
+        version: String::from_utf8_lossy(version).to_string(),
+    }))
+}
+
+fn parse_skappver(input: &[u8]) -> IResult<&[u8], Response> {
+    let (input, _) = tuple((tag("SKAPPVER"), crlf))(input)?;
+    parse_query_result(input, "SKAPPVER", parse_eappver)
+}
+
+// SKAPPVER without echo back
+fn parse_eappver(input: &[u8]) -> IResult<&[u8], Response> {
+    let (input, (_, _, version, _, _)) = tuple((
+        tag("EAPPVER"),
+        space1,
+        take_while1(|c: u8| c.is_ascii_graphic()),
+        crlf,
+        parse_ok,
+    ))(input)?;
+
+    Ok((input, Response::EAppVer {
+        version: String::from_utf8_lossy(version).to_string(),
+    }))
+}
+
+fn parse_skinfo(input: &[u8]) -> IResult<&[u8], Response> {
+    let (input, _) = tuple((tag("SKINFO"), crlf))(input)?;
+    parse_query_result(input, "SKINFO", parse_einfo)
+}
+
+// SKINFO without echo back
+fn parse_einfo(input: &[u8]) -> IResult<&[u8], Response> {
+    let (input, (_, _, ipaddr, _, addr64, _, channel, _, pan_id, _, addr16, _, _)) = tuple((
+        tag("EINFO"),
+        space1,
+        parse_ipv6_addr,
+        space1,
+        take_while1(is_alphanumeric),
+        space1,
+        map_res(hex_digit1, from_hex_u8),
+        space1,
+        map_res(hex_digit1, from_hex_u16),
+        space1,
+        map_res(hex_digit1, from_hex_u16),
+        crlf,
+        parse_ok,
+    ))(input)?;
+
+    Ok((input, Response::EInfo {
+        ipaddr,
+        addr64: String::from_utf8_lossy(addr64).to_string(),
+        channel,
+        pan_id,
+        addr16,
+    }))
+}
+
+fn parse_sktable(input: &[u8]) -> IResult<&[u8], Response> {
+    let (input, _) = tuple((tag("SKTABLE"), space1, hex_digit1, crlf))(input)?;
+    parse_query_result(input, "SKTABLE", parse_table)
+}
+
+// SKTABLE without echo back: a header line, one line per entry and OK
+fn parse_table(input: &[u8]) -> IResult<&[u8], Response> {
+    alt((
+        map(
+            preceded(tuple((tag("EADDR"), crlf)), many_till(map(tuple((parse_ipv6_addr, crlf)), |(addr, _)| addr), parse_ok)),
+            |(addrs, _)| Response::EAddr(addrs),
+        ),
+        map(
+            preceded(tuple((tag("ENEIGHBOR"), crlf)), many_till(parse_neighbor, parse_ok)),
+            |(neighbors, _)| Response::ENeighbor(neighbors),
+        ),
+    ))(input)
+}
+
+fn parse_neighbor(input: &[u8]) -> IResult<&[u8], Neighbor> {
+    let (input, (ipaddr, _, addr64, _, addr16, _)) = tuple((
+        parse_ipv6_addr,
+        space1,
+        take_while1(is_alphanumeric),
+        space1,
+        map_res(hex_digit1, from_hex_u16),
+        crlf,
+    ))(input)?;
+
+    Ok((input, Neighbor {
+        ipaddr,
+        addr64: String::from_utf8_lossy(addr64).to_string(),
+        addr16,
+    }))
+}
+
+// SKSREG with the register only reads it back
+fn parse_sksreg_read(input: &[u8]) -> IResult<&[u8], Response> {
+    let (input, _) = tuple((
+        tag("SKSREG"),
+        space1,
+        preceded(tag("S"), hex_digit1),
+        crlf,
+    ))(input)?;
+    parse_query_result(input, "SKSREG", parse_esreg)
+}
+
+// register readback without echo back
+fn parse_esreg(input: &[u8]) -> IResult<&[u8], Response> {
+    let (input, (_, _, val, _, _)) = tuple((
+        tag("ESREG"),
+        space1,
+        map_res(hex_digit1, from_hex_u32),
+        crlf,
+        parse_ok,
+    ))(input)?;
+
+    Ok((input, Response::ESReg {
+        val,
+    }))
+}
+
+fn parse_ropt(input: &[u8]) -> IResult<&[u8], Response> {
+    let (input, _) = tuple((tag("ROPT"), crlf))(input)?;
+    parse_query_result(input, "ROPT", parse_bare_ropt)
+}
+
+// ROPT without echo back
+fn parse_bare_ropt(input: &[u8]) -> IResult<&[u8], Response> {
+    let (input, (_, _, mode, _)) = tuple((
+        tag("OK"),
+        space1,
+        map_res(hex_digit1, from_hex_u8),
+        crlf,
+    ))(input)?;
+
+    Ok((input, Response::ROpt {
+        mode,
+    }))
+}
+
+fn parse_wopt(input: &[u8]) -> IResult<&[u8], Response> {
+    let (input, (_, _, mode, _)) = tuple((
+        tag("WOPT"),
+        space1,
+        map_res(hex_digit1, from_hex_u8),
+        crlf,
+    ))(input)?;
+    let (input, fail) = parse_result(input, "WOPT")?;
+    if let Some(fail) = fail {
+        return Ok((input, fail));
+    }
+
+    Ok((input, Response::WOpt {
+        mode,
+    }))
+}
+
+fn parse_bare_ipv6_addr(input: &[u8]) -> IResult<&[u8], Response> {
+    let (input, (ipaddr, _)) = tuple((parse_ipv6_addr, crlf))(input)?;
+    Ok((input, Response::Ipv6Addr(ipaddr)))
+}
+
+// accepts the responses with and without echo back, in the formats of `dialect`
+pub fn parser(input: &[u8], dialect: Dialect) -> IResult<&[u8], Response> {
     alt((
-        parse_skreset,
-        parse_sksetrbid,
-        parse_sksetpwd,
-        parse_skscan,
-        parse_event,
-        parse_epandesc,
-        parse_sksreg,
-        parse_skll64,
-        parse_skjoin,
-        parse_erxudp,
-        parse_sksendto,
+        // results without echo back
+        alt((
+            parse_ok,
+            parse_bare_ropt,
+            parse_bare_ipv6_addr,
+            parse_bare_fail,
+            parse_ever,
+            parse_eappver,
+            parse_einfo,
+            parse_table,
+            parse_esreg,
+        )),
+        alt((
+            parse_skreset,
+            parse_sksetrbid,
+            parse_sksetpwd,
+            parse_skver,
+            parse_skappver,
+            parse_skinfo,
+            parse_sktable,
+            parse_skscan,
+            parse_sksreg,
+            parse_sksreg_read,
+            parse_skll64,
+            parse_skjoin,
+            |i| parse_sksendto(i, dialect),
+            parse_ropt,
+            parse_wopt,
+        )),
+        // events
+        alt((
+            |i| parse_event(i, dialect),
+            |i| parse_epandesc(i, dialect),
+            parse_eedscan,
+            |i| parse_erxudp(i, dialect),
+        )),
     ))(input)
 }
 
@@ -633,14 +1344,14 @@ mod tests {
 
     #[test]
     fn test_parse_skreset() {
-        let (rest, response) = parser(&b"SKRESET\r\nOK\r\n"[..]).unwrap();
+        let (rest, response) = parser(&b"SKRESET\r\nOK\r\n"[..], Dialect::Bp35a1).unwrap();
         assert_eq!(rest, &b""[..]);
         assert_eq!(response, Response::SkReset);
     }
 
     #[test]
     fn test_parse_sksetrbid() {
-        let (rest, response) = parser(&b"SKSETRBID 11111122222222333333334444444AAA\r\nOK\r\n"[..]).unwrap();
+        let (rest, response) = parser(&b"SKSETRBID 11111122222222333333334444444AAA\r\nOK\r\n"[..], Dialect::Bp35a1).unwrap();
         assert_eq!(rest, &b""[..]);
         assert_eq!(response, Response::SkSetRbid {
             id: "11111122222222333333334444444AAA".to_string()
@@ -680,7 +1391,7 @@ mod tests {
 
     #[test]
     fn test_parse_sksetpwd() {
-        let (rest, response) = parser(&b"SKSETPWD C 123XXXXXXXXX\r\nOK\r\n"[..]).unwrap();
+        let (rest, response) = parser(&b"SKSETPWD C 123XXXXXXXXX\r\nOK\r\n"[..], Dialect::Bp35a1).unwrap();
         assert_eq!(rest, &b""[..]);
         assert_eq!(response, Response::SkSetPwd {
             len: 0x0c,
@@ -690,13 +1401,13 @@ mod tests {
 
     #[test]
     fn test_parse_sksetpwd_wrong_length_field() {
-        let res = parser(&b"SKSETPWD F 123XXXXXXXXX\r\nOK\r\n"[..]);
+        let res = parser(&b"SKSETPWD F 123XXXXXXXXX\r\nOK\r\n"[..], Dialect::Bp35a1);
         assert_eq!(res, Err(nom::Err::Failure(nom::error::Error::new(&b""[..], nom::error::ErrorKind::Verify))));
     }
 
     #[test]
     fn test_parse_skscan() {
-        let (rest, response) = parser(&b"SKSCAN 2 FFFFFFFF 6\r\nOK\r\n"[..]).unwrap();
+        let (rest, response) = parser(&b"SKSCAN 2 FFFFFFFF 6\r\nOK\r\n"[..], Dialect::Bp35a1).unwrap();
         assert_eq!(rest, &b""[..]);
         assert_eq!(response, Response::SkScan {
             mode: 2,
@@ -714,34 +1425,80 @@ mod tests {
 
     #[test]
     fn test_parse_event() {
-        let (rest, response) = parser(&b"EVENT 20 FE80:0000:0000:0000:0123:4567:89ab:cdef\r\n"[..]).unwrap();
+        let (rest, response) = parser(&b"EVENT 20 FE80:0000:0000:0000:0123:4567:89ab:cdef\r\n"[..], Dialect::Bp35a1).unwrap();
+        assert_eq!(rest, &b""[..]);
+        assert_eq!(response, Response::Event {
+            event: Event::BeaconReceived,
+            sender: "FE80:0000:0000:0000:0123:4567:89ab:cdef".to_string(),
+        });
+
+        let (rest, response) = parser(&b"EVENT 22 FE80:0000:0000:0000:0123:4567:89ab:cdef\r\n"[..], Dialect::Bp35a1).unwrap();
         assert_eq!(rest, &b""[..]);
         assert_eq!(response, Response::Event {
-            num: 0x20,
+            event: Event::ActiveScanDone,
             sender: "FE80:0000:0000:0000:0123:4567:89ab:cdef".to_string(),
-            param: None,
         });
 
-        let (rest, response) = parser(&b"EVENT 22 FE80:0000:0000:0000:0123:4567:89ab:cdef\r\n"[..]).unwrap();
+        let (rest, response) = parser(&b"EVENT 21 FE80:0000:0000:0000:0123:4567:89ab:cdef 02\r\n"[..], Dialect::Bp35a1).unwrap();
         assert_eq!(rest, &b""[..]);
         assert_eq!(response, Response::Event {
-            num: 0x22,
+            event: Event::UdpSent(SendResult::NeighborSolicitation),
             sender: "FE80:0000:0000:0000:0123:4567:89ab:cdef".to_string(),
-            param: None,
         });
 
-        let (rest, response) = parser(&b"EVENT 21 FE80:0000:0000:0000:0123:4567:89ab:cdef 02\r\n"[..]).unwrap();
+        let (rest, response) = parser(&b"EVENT 29 FE80:0000:0000:0000:0123:4567:89ab:cdef\r\n"[..], Dialect::Bp35a1).unwrap();
         assert_eq!(rest, &b""[..]);
         assert_eq!(response, Response::Event {
-            num: 0x21,
+            event: Event::PanaSessionExpired,
             sender: "FE80:0000:0000:0000:0123:4567:89ab:cdef".to_string(),
-            param: Some(0x02),
         });
+
+        let (_, response) = parser(&b"EVENT 45 FE80:0000:0000:0000:0123:4567:89ab:cdef 01\r\n"[..], Dialect::Bp35a1).unwrap();
+        assert!(matches!(response, Response::Event { event: Event::Unknown { num: 0x45, param: Some(0x01) }, .. }));
+    }
+
+    #[test]
+    fn test_event_new() {
+        for (num, param, event, kind) in [
+            (0x01, None, Event::NsReceived, "ns_received"),
+            (0x02, None, Event::NaReceived, "na_received"),
+            (0x05, None, Event::EchoRequestReceived, "echo_request_received"),
+            (0x1F, None, Event::EdScanDone, "ed_scan_done"),
+            (0x20, None, Event::BeaconReceived, "beacon_received"),
+            (0x21, Some(0x00), Event::UdpSent(SendResult::Success), "udp_sent"),
+            (0x21, Some(0x01), Event::UdpSent(SendResult::Failure), "udp_sent"),
+            (0x21, Some(0x02), Event::UdpSent(SendResult::NeighborSolicitation), "udp_sent"),
+            (0x21, Some(0x03), Event::UdpSent(SendResult::Unknown(0x03)), "udp_sent"),
+            (0x22, None, Event::ActiveScanDone, "active_scan_done"),
+            (0x24, None, Event::PanaConnectFailed, "pana_connect_failed"),
+            (0x25, None, Event::PanaConnected, "pana_connected"),
+            (0x26, None, Event::PanaSessionTerminationRequested, "pana_session_termination_requested"),
+            (0x27, None, Event::PanaSessionTerminated, "pana_session_terminated"),
+            (0x28, None, Event::PanaSessionTerminationTimedOut, "pana_session_termination_timed_out"),
+            (0x29, None, Event::PanaSessionExpired, "pana_session_expired"),
+            (0x32, None, Event::TransmissionLimited, "transmission_limited"),
+            (0x33, None, Event::TransmissionLimitReleased, "transmission_limit_released"),
+            (0x45, Some(0x01), Event::Unknown { num: 0x45, param: Some(0x01) }, "unknown"),
+            // EVENT 21 always carries the result
+            (0x21, None, Event::Unknown { num: 0x21, param: None }, "unknown"),
+        ] {
+            assert_eq!(Event::new(num, param), event);
+            assert_eq!(event.kind(), kind);
+        }
+    }
+
+    #[test]
+    fn test_rssi_dbm() {
+        let pan_desc = PanDesc { lqi: 0xe1, ..Default::default() };
+        assert!((pan_desc.rssi_dbm() - (-42.395)).abs() < 1e-9);
+
+        let pan_desc = PanDesc { lqi: 0xe1, rssi: Some(-75), ..Default::default() };
+        assert_eq!(pan_desc.rssi_dbm(), -75.0);
     }
 
     #[test]
     fn test_parse_epandesc() {
-        let (rest, epandesc) = parser(&b"EPANDESC\r\n  Channel:21\r\n  Channel Page:09\r\n  Pan ID:8888\r\n  Addr:001D129012345678\r\n  LQI:E1\r\n  PairID:00AXXXXX\r\n"[..]).unwrap();
+        let (rest, epandesc) = parser(&b"EPANDESC\r\n  Channel:21\r\n  Channel Page:09\r\n  Pan ID:8888\r\n  Addr:001D129012345678\r\n  LQI:E1\r\n  PairID:00AXXXXX\r\n"[..], Dialect::Bp35a1).unwrap();
         assert_eq!(rest, &b""[..]);
         assert_eq!(epandesc, Response::EPanDesc(PanDesc {
             channel: 0x21,
@@ -749,20 +1506,83 @@ mod tests {
             pan_id: 0x8888,
             addr: "001D129012345678".to_string(),
             lqi: 0xe1,
+            rssi: None,
             pair_id: "00AXXXXX".to_string(),
         }));
     }
 
+    #[test]
+    fn test_parse_eedscan() {
+        let (rest, response) = parser(&b"EEDSCAN\r\n21 4D 22 2F 3C 00\r\n"[..], Dialect::Bp35a1).unwrap();
+        assert_eq!(rest, &b""[..]);
+        assert_eq!(response, Response::EEdScan(vec![
+            ChannelEnergy { channel: 0x21, level: 0x4d },
+            ChannelEnergy { channel: 0x22, level: 0x2f },
+            ChannelEnergy { channel: 0x3c, level: 0x00 },
+        ]));
+
+        assert!(matches!(parser(&b"EEDSCAN\r\n21 4D 22 2F"[..], Dialect::Bp35a1), Err(nom::Err::Incomplete(_))));
+    }
+
+    #[test]
+    fn test_parse_without_echo_back() {
+        let (rest, response) = parser(&b"OK\r\n"[..], Dialect::Bp35a1).unwrap();
+        assert_eq!(rest, &b""[..]);
+        assert_eq!(response, Response::Ok);
+
+        let (rest, response) = parser(&b"FE80:0000:0000:0000:0123:4567:89ab:cdef\r\n"[..], Dialect::Bp35a1).unwrap();
+        assert_eq!(rest, &b""[..]);
+        assert_eq!(response, Response::Ipv6Addr("FE80:0000:0000:0000:0123:4567:89ab:cdef".to_string()));
+
+        // SKSENDTO
+        let (rest, response) = parser(&b"EVENT 21 FE80:0000:0000:0000:0123:4567:89ab:cdef 00\r\nOK\r\n"[..], Dialect::Bp35a1).unwrap();
+        assert_eq!(response, Response::Event {
+            event: Event::UdpSent(SendResult::Success),
+            sender: "FE80:0000:0000:0000:0123:4567:89ab:cdef".to_string(),
+        });
+        let (rest, response) = parser(rest, Dialect::Bp35a1).unwrap();
+        assert_eq!(rest, &b""[..]);
+        assert_eq!(response, Response::Ok);
+    }
+
+    #[test]
+    fn test_parse_fail() {
+        let (rest, response) = parser(&b"SKSREG S2 FF\r\nFAIL ER06\r\n"[..], Dialect::Bp35a1).unwrap();
+        assert_eq!(rest, &b""[..]);
+        assert_eq!(response, Response::Fail {
+            command: Some("SKSREG".to_string()),
+            code: FailCode::ParameterOutOfRange,
+        });
+
+        let (rest, response) = parser(&b"SKSENDTO 1 FE80:0000:0000:0000:0123:4567:89ab:cdef 0E1A 1 000E \r\nFAIL ER10\r\n"[..], Dialect::Bp35a1).unwrap();
+        assert_eq!(rest, &b""[..]);
+        assert_eq!(response, Response::Fail {
+            command: Some("SKSENDTO".to_string()),
+            code: FailCode::ExecutionFailed,
+        });
+
+        let (rest, response) = parser(&b"FAIL ER04\r\n"[..], Dialect::Bp35a1).unwrap();
+        assert_eq!(rest, &b""[..]);
+        assert_eq!(response, Response::Fail {
+            command: None,
+            code: FailCode::UnsupportedCommand,
+        });
+
+        assert!(matches!(parser(&b"SKSREG S2 FF\r\nFA"[..], Dialect::Bp35a1), Err(nom::Err::Incomplete(_))));
+        assert_eq!(FailCode::from(0x63).label(), "ER99");
+        assert_eq!(FailCode::ParameterOutOfRange.to_string(), "ER06 (parameter out of range)");
+    }
+
     #[test]
     fn test_parse_sksreg() {
-        let (rest, response) = parser(&b"SKSREG S2 1A\r\nOK\r\n"[..]).unwrap();
+        let (rest, response) = parser(&b"SKSREG S2 1A\r\nOK\r\n"[..], Dialect::Bp35a1).unwrap();
         assert_eq!(rest, &b""[..]);
         assert_eq!(response, Response::SkSreg {
             sreg: 2,
             val: 0x1a,
         });
 
-        let (rest, response) = parser(&b"SKSREG S3 EF66\r\nOK\r\n"[..]).unwrap();
+        let (rest, response) = parser(&b"SKSREG S3 EF66\r\nOK\r\n"[..], Dialect::Bp35a1).unwrap();
         assert_eq!(rest, &b""[..]);
         assert_eq!(response, Response::SkSreg {
             sreg: 3,
@@ -772,7 +1592,7 @@ mod tests {
 
     #[test]
     fn test_parse_skll64() {
-        let (rest, response) = parser(&b"SKLL64 0123456789ABCDEF\r\nFE80:0000:0000:0000:0123:4567:89ab:cdef\r\n"[..]).unwrap();
+        let (rest, response) = parser(&b"SKLL64 0123456789ABCDEF\r\nFE80:0000:0000:0000:0123:4567:89ab:cdef\r\n"[..], Dialect::Bp35a1).unwrap();
         assert_eq!(rest, &b""[..]);
         assert_eq!(response, Response::SkLl64 {
             addr64: "0123456789ABCDEF".to_string(),
@@ -782,7 +1602,7 @@ mod tests {
 
     #[test]
     fn test_parse_skjion() {
-        let (rest, response) = parser(&b"SKJOIN FE80:0000:0000:0000:0123:4567:89ab:cdef\r\nOK\r\n"[..]).unwrap();
+        let (rest, response) = parser(&b"SKJOIN FE80:0000:0000:0000:0123:4567:89ab:cdef\r\nOK\r\n"[..], Dialect::Bp35a1).unwrap();
         assert_eq!(rest, &b""[..]);
         assert_eq!(response, Response::SkJoin {
             ipaddr: "FE80:0000:0000:0000:0123:4567:89ab:cdef".to_string(),
@@ -826,7 +1646,7 @@ mod tests {
                 class_code: 0x88,
                 instance_code: 0x01,
             },
-            esv: 0x62,
+            esv: Esv::Get,
             opc: 0x01,
             props: vec![EDataProperty {
                 epc: 0xe7,
@@ -836,9 +1656,16 @@ mod tests {
         }));
     }
 
+    #[test]
+    fn test_parse_edata_unknown_esv() {
+        let (rest, edata) = parse_edata(&b"\x05\xff\x01\x02\x88\x01\x6f\x01\xe7\x00"[..]).unwrap();
+        assert_eq!(rest, &b""[..]);
+        assert!(matches!(edata, EData::EDataFormat1(EDataFormat1 { esv: Esv::Unknown(0x6f), opc: 0x01, .. })));
+    }
+
     #[test]
     fn test_parse_erxudp() {
-        let (rest, response) = parser(&b"ERXUDP FE80:0000:0000:0000:0123:4567:89ab:cdef FE80:0000:0000:0000:3210:7654:ba98:fedc 0E1A 0E1A 001D129012345678 1 0012 \x10\x81\0\x01\x02\x88\x01\x05\xff\x01r\x01\xe7\x04\0\0\x01\xa8\r\n"[..]).unwrap();
+        let (rest, response) = parser(&b"ERXUDP FE80:0000:0000:0000:0123:4567:89ab:cdef FE80:0000:0000:0000:3210:7654:ba98:fedc 0E1A 0E1A 001D129012345678 1 0012 \x10\x81\0\x01\x02\x88\x01\x05\xff\x01r\x01\xe7\x04\0\0\x01\xa8\r\n"[..], Dialect::Bp35a1).unwrap();
 
         assert_eq!(rest, &b""[..]);
         assert_eq!(response, Response::ERxUdp {
@@ -847,9 +1674,10 @@ mod tests {
             rport: 0xe1a,
             lport: 0xe1a,
             senderlla: "001D129012345678".to_string(),
+            rssi: None,
             secured: 0x01,
             datalen: 0x012,
-            data: EchonetLite {
+            data: UdpPayload::EchonetLite(EchonetLite {
                 ehd: EHd {
                     ehd1: 0x10,
                     ehd2: 0x81,
@@ -866,7 +1694,7 @@ mod tests {
                         class_code: 0xff,
                         instance_code: 0x01,
                     },
-                    esv: 0x72,
+                    esv: Esv::GetRes,
                     opc: 0x01,
                     props: vec![EDataProperty {
                         epc: 0xe7,
@@ -874,14 +1702,127 @@ mod tests {
                         edt: Bytes::from_static(b"\0\0\x01\xa8"),
                     }],
                 })
-            }
+            }),
+        });
+
+    }
+
+    #[test]
+    fn test_parse_erxudp_ascii() {
+        let binary = parser(&b"ERXUDP FE80:0000:0000:0000:0123:4567:89ab:cdef FE80:0000:0000:0000:3210:7654:ba98:fedc 0E1A 0E1A 001D129012345678 1 0012 \x10\x81\0\x01\x02\x88\x01\x05\xff\x01r\x01\xe7\x04\0\0\x01\xa8\r\n"[..], Dialect::Bp35a1).unwrap().1;
+
+        let input = &b"ERXUDP FE80:0000:0000:0000:0123:4567:89ab:cdef FE80:0000:0000:0000:3210:7654:ba98:fedc 0E1A 0E1A 001D129012345678 1 0012 1081000102880105FF017201E704000001A8\r\n"[..];
+        let (rest, response) = parser(input, Dialect::Bp35a1).unwrap();
+        assert_eq!(rest, &b""[..]);
+        assert_eq!(response, binary);
+
+        // half of the hex digits is as long as the raw payload, it must wait for the rest
+        assert!(matches!(parser(&input[..input.len() - 20], Dialect::Bp35a1), Err(nom::Err::Incomplete(_))));
+    }
+
+    #[test]
+    fn test_parse_skver() {
+        let (rest, response) = parser(&b"SKVER\r\nEVER 1.2.10\r\nOK\r\n"[..], Dialect::Bp35a1).unwrap();
+        assert_eq!(rest, &b""[..]);
+        assert_eq!(response, Response::EVer { version: "1.2.10".to_string() });
+
+        let (rest, response) = parser(&b"EVER 1.5.2\r\nOK\r\n"[..], Dialect::Bp35a1).unwrap();
+        assert_eq!(rest, &b""[..]);
+        assert_eq!(response, Response::EVer { version: "1.5.2".to_string() });
+    }
+
+    #[test]
+    fn test_parse_module_info() {
+        let (rest, response) = parser(&b"SKAPPVER\r\nEAPPVER rev26e\r\nOK\r\n"[..], Dialect::Bp35a1).unwrap();
+        assert_eq!(rest, &b""[..]);
+        assert_eq!(response, Response::EAppVer { version: "rev26e".to_string() });
+
+        let (rest, response) = parser(&b"SKINFO\r\nEINFO FE80:0000:0000:0000:021D:1290:0003:C890 001D129000030C89 21 8888 FFFE\r\nOK\r\n"[..], Dialect::Bp35a1).unwrap();
+        assert_eq!(rest, &b""[..]);
+        assert_eq!(response, Response::EInfo {
+            ipaddr: "FE80:0000:0000:0000:021D:1290:0003:C890".to_string(),
+            addr64: "001D129000030C89".to_string(),
+            channel: 0x21,
+            pan_id: 0x8888,
+            addr16: 0xfffe,
         });
 
+        let (rest, response) = parser(&b"SKTABLE 1\r\nEADDR\r\nFE80:0000:0000:0000:021D:1290:0003:C890\r\nOK\r\n"[..], Dialect::Bp35a1).unwrap();
+        assert_eq!(rest, &b""[..]);
+        assert_eq!(response, Response::EAddr(vec!["FE80:0000:0000:0000:021D:1290:0003:C890".to_string()]));
+
+        let (rest, response) = parser(&b"ENEIGHBOR\r\nFE80:0000:0000:0000:0123:4567:89ab:cdef 0123456789ABCDEF FFFF\r\nOK\r\n"[..], Dialect::Bp35a1).unwrap();
+        assert_eq!(rest, &b""[..]);
+        assert_eq!(response, Response::ENeighbor(vec![Neighbor {
+            ipaddr: "FE80:0000:0000:0000:0123:4567:89ab:cdef".to_string(),
+            addr64: "0123456789ABCDEF".to_string(),
+            addr16: 0xffff,
+        }]));
+
+        // the table may not be complete yet
+        assert!(matches!(parser(&b"SKTABLE 2\r\nENEIGHBOR\r\nFE80:0000:0000:0000:0123:4567:89ab:cdef 0123456789ABCDEF FFFF\r\n"[..], Dialect::Bp35a1), Err(nom::Err::Incomplete(_))));
+
+        let (rest, response) = parser(&b"SKSREG SFE\r\nESREG 1\r\nOK\r\n"[..], Dialect::Bp35a1).unwrap();
+        assert_eq!(rest, &b""[..]);
+        assert_eq!(response, Response::ESReg { val: 1 });
+
+        let (_, response) = parser(&b"SKINFO\r\nFAIL ER10\r\n"[..], Dialect::Bp35a1).unwrap();
+        assert_eq!(response, Response::Fail { command: Some("SKINFO".to_string()), code: FailCode::ExecutionFailed });
+    }
+
+    #[test]
+    fn test_parse_opt() {
+        let (rest, response) = parser(&b"ROPT\r\nOK 01\r\n"[..], Dialect::Bp35a1).unwrap();
+        assert_eq!(rest, &b""[..]);
+        assert_eq!(response, Response::ROpt { mode: 0x01 });
+
+        let (rest, response) = parser(&b"OK 00\r\n"[..], Dialect::Bp35a1).unwrap();
+        assert_eq!(rest, &b""[..]);
+        assert_eq!(response, Response::ROpt { mode: 0x00 });
+
+        let (rest, response) = parser(&b"WOPT 01\r\nOK\r\n"[..], Dialect::Bp35a1).unwrap();
+        assert_eq!(rest, &b""[..]);
+        assert_eq!(response, Response::WOpt { mode: 0x01 });
+
+        let (_, response) = parser(&b"ROPT\r\nFAIL ER04\r\n"[..], Dialect::Bp35a1).unwrap();
+        assert_eq!(response, Response::Fail { command: Some("ROPT".to_string()), code: FailCode::UnsupportedCommand });
+    }
+
+    #[test]
+    fn test_parse_bp35c0() {
+        let (rest, response) = parser(&b"ERXUDP FE80:0000:0000:0000:0123:4567:89ab:cdef FE80:0000:0000:0000:3210:7654:ba98:fedc 0E1A 0E1A 001D129012345678 B5 1 0 0012 \x10\x81\0\x01\x02\x88\x01\x05\xff\x01r\x01\xe7\x04\0\0\x01\xa8\r\n"[..], Dialect::Bp35c0).unwrap();
+        assert_eq!(rest, &b""[..]);
+        assert!(matches!(response, Response::ERxUdp { rssi: Some(-75), secured: 0x01, datalen: 0x12, data: UdpPayload::EchonetLite(_), .. }));
+
+        // RSSI is only there if WOPT enables it
+        let (rest, response) = parser(&b"ERXUDP FE80:0000:0000:0000:0123:4567:89ab:cdef FE80:0000:0000:0000:3210:7654:ba98:fedc 0E1A 0E1A 001D129012345678 1 0 0012 \x10\x81\0\x01\x02\x88\x01\x05\xff\x01r\x01\xe7\x04\0\0\x01\xa8\r\n"[..], Dialect::Bp35c0).unwrap();
+        assert_eq!(rest, &b""[..]);
+        assert!(matches!(response, Response::ERxUdp { rssi: None, secured: 0x01, datalen: 0x12, data: UdpPayload::EchonetLite(_), .. }));
+
+        let (rest, response) = parser(&b"EPANDESC\r\n  Channel:21\r\n  Channel Page:09\r\n  Pan ID:8888\r\n  Addr:001D129012345678\r\n  LQI:E1\r\n  RSSI:B5\r\n  PairID:00AXXXXX\r\n"[..], Dialect::Bp35c0).unwrap();
+        assert_eq!(rest, &b""[..]);
+        assert!(matches!(response, Response::EPanDesc(PanDesc { lqi: 0xe1, rssi: Some(-75), .. })));
+
+        // RSSI is optional in EPANDESC
+        let (_, response) = parser(&b"EPANDESC\r\n  Channel:21\r\n  Channel Page:09\r\n  Pan ID:8888\r\n  Addr:001D129012345678\r\n  LQI:E1\r\n  PairID:00AXXXXX\r\n"[..], Dialect::Bp35c0).unwrap();
+        assert!(matches!(response, Response::EPanDesc(PanDesc { rssi: None, .. })));
+
+        let (rest, response) = parser(&b"SKSENDTO 1 FE80:0000:0000:0000:0123:4567:89ab:cdef 0E1A 1 0 000E \r\nEVENT 21 FE80:0000:0000:0000:0123:4567:89ab:cdef 0 00\r\nOK\r\n\r\n"[..], Dialect::Bp35c0).unwrap();
+        assert_eq!(rest, &b""[..]);
+        assert!(matches!(response, Response::SkSendTo { datalen: 0x0e, result: SendResult::Success, .. }));
+
+        let (_, response) = parser(&b"EVENT 25 FE80:0000:0000:0000:0123:4567:89ab:cdef 0\r\n"[..], Dialect::Bp35c0).unwrap();
+        assert!(matches!(response, Response::Event { event: Event::PanaConnected, .. }));
+        let (_, response) = parser(&b"EVENT 21 FE80:0000:0000:0000:0123:4567:89ab:cdef 0 02\r\n"[..], Dialect::Bp35c0).unwrap();
+        assert!(matches!(response, Response::Event { event: Event::UdpSent(SendResult::NeighborSolicitation), .. }));
+
+        let (_, response) = parser(&b"SKSCAN 2 FFFFFFFF 6 0\r\nOK\r\n"[..], Dialect::Bp35c0).unwrap();
+        assert!(matches!(response, Response::SkScan { duration: 6, .. }));
     }
 
     #[test]
     fn test_parse_erxudp_invalid_frame() {
-        let (rest, response) = parser(&b"ERXUDP FE80:0000:0000:0000:0123:4567:89ab:cdef FE80:0000:0000:0000:3210:7654:ba98:fedc 02CC 02CC 001D129012345678 0 0028 \0\0\0(\xc0\0\0\x02\x06\x04S\x07\x8d\xd5a\xbf\0\x06\0\0\0\x04\0\0\0\0\0\x05\0\x03\0\0\0\x04\0\0\0\0\0\x0c\r\n"[..]).unwrap();
+        let (rest, response) = parser(&b"ERXUDP FE80:0000:0000:0000:0123:4567:89ab:cdef FE80:0000:0000:0000:3210:7654:ba98:fedc 02CC 02CC 001D129012345678 0 0028 \0\0\0(\xc0\0\0\x02\x06\x04S\x07\x8d\xd5a\xbf\0\x06\0\0\0\x04\0\0\0\0\0\x05\0\x03\0\0\0\x04\0\0\0\0\0\x0c\r\n"[..], Dialect::Bp35a1).unwrap();
 
         assert_eq!(rest, &b""[..]);
         assert_eq!(response, Response::ERxUdp {
@@ -890,20 +1831,14 @@ mod tests {
             rport: 0x2cc,
             lport: 0x2cc,
             senderlla: "001D129012345678".to_string(),
+            rssi: None,
             secured: 0x00,
             datalen: 0x028,
-            data: EchonetLite {
-                ehd: EHd {
-                    ehd1: 0x00,
-                    ehd2: 0x00,
-                    tid: 0x0028,
-                },
-                edata: EData::InvalidEData(Bytes::from_static(b"\xc0\0\0\x02\x06\x04S\x07\x8d\xd5a\xbf\0\x06\0\0\0\x04\0\0\0\0\0\x05\0\x03\0\0\0\x04\0\0\0\0\0\x0c"))
-            }
+            data: UdpPayload::Other(Bytes::from_static(b"\0\0\0(\xc0\0\0\x02\x06\x04S\x07\x8d\xd5a\xbf\0\x06\0\0\0\x04\0\0\0\0\0\x05\0\x03\0\0\0\x04\0\0\0\0\0\x0c")),
         });
 
 
-        let (rest, response) = parser(&b"ERXUDP FE80:0000:0000:0000:0123:4567:89ab:cdef FE80:0000:0000:0000:3210:7654:ba98:fedc 02CC 02CC 001D129012345678 0 0058 \0\0\0X\xa0\0\0\x02\x06\x04S\x07\x8d\xd5a\xc2\0\x07\0\0\0\x04\0\0\0\0\0\0\0\x02\0\0\0\x04\0\0\x03\xb5\0\x04\0\x04\0\0\0\x04\0\0\0\0\x07\x01\0\x08\0\0\0\x04\0\0\0\x01Q\x80\0\x01\0\0\0\x10\0\0\x13v\x01$1\x1c\x90\xd3T\xb6p 83\xee\xe7\r\n"[..]).unwrap();
+        let (rest, response) = parser(&b"ERXUDP FE80:0000:0000:0000:0123:4567:89ab:cdef FE80:0000:0000:0000:3210:7654:ba98:fedc 02CC 02CC 001D129012345678 0 0058 \0\0\0X\xa0\0\0\x02\x06\x04S\x07\x8d\xd5a\xc2\0\x07\0\0\0\x04\0\0\0\0\0\0\0\x02\0\0\0\x04\0\0\x03\xb5\0\x04\0\x04\0\0\0\x04\0\0\0\0\x07\x01\0\x08\0\0\0\x04\0\0\0\x01Q\x80\0\x01\0\0\0\x10\0\0\x13v\x01$1\x1c\x90\xd3T\xb6p 83\xee\xe7\r\n"[..], Dialect::Bp35a1).unwrap();
         assert_eq!(rest, &b""[..]);
         assert_eq!(response, Response::ERxUdp {
             sender: "FE80:0000:0000:0000:0123:4567:89ab:cdef".to_string(),
@@ -911,22 +1846,41 @@ mod tests {
             rport: 0x2cc,
             lport: 0x2cc,
             senderlla: "001D129012345678".to_string(),
+            rssi: None,
             secured: 0x00,
             datalen: 0x058,
-            data: EchonetLite {
-                ehd: EHd {
-                    ehd1: 0x00,
-                    ehd2: 0x00,
-                    tid: 0x0058,
-                },
-                edata: EData::InvalidEData(Bytes::from_static(b"\xa0\0\0\x02\x06\x04S\x07\x8d\xd5a\xc2\0\x07\0\0\0\x04\0\0\0\0\0\0\0\x02\0\0\0\x04\0\0\x03\xb5\0\x04\0\x04\0\0\0\x04\0\0\0\0\x07\x01\0\x08\0\0\0\x04\0\0\0\x01Q\x80\0\x01\0\0\0\x10\0\0\x13v\x01$1\x1c\x90\xd3T\xb6p 83\xee\xe7"))
-            }
+            data: UdpPayload::Other(Bytes::from_static(b"\0\0\0X\xa0\0\0\x02\x06\x04S\x07\x8d\xd5a\xc2\0\x07\0\0\0\x04\0\0\0\0\0\0\0\x02\0\0\0\x04\0\0\x03\xb5\0\x04\0\x04\0\0\0\x04\0\0\0\0\x07\x01\0\x08\0\0\0\x04\0\0\0\x01Q\x80\0\x01\0\0\0\x10\0\0\x13v\x01$1\x1c\x90\xd3T\xb6p 83\xee\xe7")),
         });
     }
 
+    #[test]
+    fn test_parse_erxudp_format2() {
+        let (rest, response) = parser(&b"ERXUDP FE80:0000:0000:0000:0123:4567:89ab:cdef FE80:0000:0000:0000:3210:7654:ba98:fedc 0E1A 0E1A 001D129012345678 1 0007 \x10\x82\0\x01\xaa\xbb\xcc\r\n"[..], Dialect::Bp35a1).unwrap();
+        assert_eq!(rest, &b""[..]);
+        assert!(matches!(response, Response::ERxUdp {
+            data: UdpPayload::EchonetLite(EchonetLite {
+                ehd: EHd { ehd1: 0x10, ehd2: 0x82, tid: 0x0001 },
+                edata: EData::EDataFormat2(edata),
+            }), ..
+        } if edata == Bytes::from_static(b"\xaa\xbb\xcc")));
+    }
+
+    #[test]
+    fn test_parse_erxudp_short_or_malformed_frame() {
+        // shorter than EHD
+        let (rest, response) = parser(&b"ERXUDP FE80:0000:0000:0000:0123:4567:89ab:cdef FE80:0000:0000:0000:3210:7654:ba98:fedc 0E1A 0E1A 001D129012345678 1 0002 \x10\x81\r\n"[..], Dialect::Bp35a1).unwrap();
+        assert_eq!(rest, &b""[..]);
+        assert!(matches!(response, Response::ERxUdp { data: UdpPayload::Other(data), .. } if data == Bytes::from_static(b"\x10\x81")));
+
+        // OPC says there are two properties, but there is only one
+        let (rest, response) = parser(&b"ERXUDP FE80:0000:0000:0000:0123:4567:89ab:cdef FE80:0000:0000:0000:3210:7654:ba98:fedc 0E1A 0E1A 001D129012345678 1 000E \x10\x81\0\x01\x02\x88\x01\x05\xff\x01r\x02\xe7\x00\r\n"[..], Dialect::Bp35a1).unwrap();
+        assert_eq!(rest, &b""[..]);
+        assert!(matches!(response, Response::ERxUdp { data: UdpPayload::Other(_), .. }));
+    }
+
     #[test]
     fn test_parse_sksendto() {
-        let (rest, response) = parser(&b"SKSENDTO 1 FE80:0000:0000:0000:0123:4567:89ab:cdef 0E1A 1 000e \r\nEVENT 21 FE80:0000:0000:0000:0123:4567:89ab:cdef 00\r\nOK\r\n\r\n"[..]).unwrap();
+        let (rest, response) = parser(&b"SKSENDTO 1 FE80:0000:0000:0000:0123:4567:89ab:cdef 0E1A 1 000e \r\nEVENT 21 FE80:0000:0000:0000:0123:4567:89ab:cdef 00\r\nOK\r\n\r\n"[..], Dialect::Bp35a1).unwrap();
         assert_eq!(rest, &b""[..]);
         assert_eq!(response, Response::SkSendTo {
             handle: 0x1,
@@ -934,7 +1888,7 @@ mod tests {
             port: 0xe1a,
             sec: 0x1,
             datalen: 0x0e,
-            result: 0x00,
+            result: SendResult::Success,
         });
     }
 }
\ No newline at end of file
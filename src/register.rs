@@ -0,0 +1,189 @@
+use std::error::Error;
+use std::fmt;
+
+// addresses of the virtual registers of SKSTACK IP
+pub struct Sreg;
+impl Sreg {
+    pub const CHANNEL: u8 = 0x02;
+    pub const PAN_ID: u8 = 0x03;
+    pub const FRAME_COUNTER: u8 = 0x07;
+    pub const PAIRING_ID: u8 = 0x0A;
+    pub const BEACON_RESPONSE: u8 = 0x15;
+    pub const PANA_SESSION_LIFETIME: u8 = 0x16;
+    pub const AUTO_REAUTHENTICATION: u8 = 0x17;
+    pub const ENCRYPT_BROADCAST: u8 = 0xA0;
+    pub const ICMP_ECHO_REPLY: u8 = 0xA1;
+    pub const TRANSMISSION_LIMITED: u8 = 0xFB;
+    pub const TRANSMISSION_TIME: u8 = 0xFD;
+    pub const ECHO_BACK: u8 = 0xFE;
+    pub const AUTO_LOAD: u8 = 0xFF;
+}
+
+// channels of the 920MHz band
+pub const CHANNEL_MIN: u8 = 0x21;
+pub const CHANNEL_MAX: u8 = 0x3C;
+pub const PANA_SESSION_LIFETIME_MIN: u32 = 0x3C;
+
+// a register with its value
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Register {
+    Channel(u8),
+    PanId(u16),
+    // read only
+    FrameCounter(u32),
+    // 8 hex digits, used to pick the coordinator in an active scan
+    PairingId(u32),
+    BeaconResponse(bool),
+    // seconds
+    PanaSessionLifetime(u32),
+    AutoReauthentication(bool),
+    EncryptBroadcast(bool),
+    IcmpEchoReply(bool),
+    // read only, set while sending is suspended by the transmission time limit
+    TransmissionLimited(bool),
+    // read only, ms sent in the last hour
+    TransmissionTime(u32),
+    EchoBack(bool),
+    AutoLoad(bool),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum RegisterError {
+    UnknownRegister(u8),
+    ReadOnly(u8),
+    OutOfRange(Register),
+    InvalidValue { sreg: u8, val: u32 },
+}
+
+impl fmt::Display for RegisterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegisterError::UnknownRegister(sreg) => write!(f, "unknown register S{:02X}", sreg),
+            RegisterError::ReadOnly(sreg) => write!(f, "register S{:02X} is read only", sreg),
+            RegisterError::OutOfRange(register) => write!(f, "value out of range: {:?}", register),
+            RegisterError::InvalidValue { sreg, val } => write!(f, "invalid value of register S{:02X}: {:#x}", sreg, val),
+        }
+    }
+}
+
+impl Error for RegisterError {}
+
+fn flag(sreg: u8, val: u32) -> Result<bool, RegisterError> {
+    match val {
+        0 => Ok(false),
+        1 => Ok(true),
+        val => Err(RegisterError::InvalidValue { sreg, val }),
+    }
+}
+
+impl Register {
+    pub fn sreg(&self) -> u8 {
+        match self {
+            Register::Channel(_) => Sreg::CHANNEL,
+            Register::PanId(_) => Sreg::PAN_ID,
+            Register::FrameCounter(_) => Sreg::FRAME_COUNTER,
+            Register::PairingId(_) => Sreg::PAIRING_ID,
+            Register::BeaconResponse(_) => Sreg::BEACON_RESPONSE,
+            Register::PanaSessionLifetime(_) => Sreg::PANA_SESSION_LIFETIME,
+            Register::AutoReauthentication(_) => Sreg::AUTO_REAUTHENTICATION,
+            Register::EncryptBroadcast(_) => Sreg::ENCRYPT_BROADCAST,
+            Register::IcmpEchoReply(_) => Sreg::ICMP_ECHO_REPLY,
+            Register::TransmissionLimited(_) => Sreg::TRANSMISSION_LIMITED,
+            Register::TransmissionTime(_) => Sreg::TRANSMISSION_TIME,
+            Register::EchoBack(_) => Sreg::ECHO_BACK,
+            Register::AutoLoad(_) => Sreg::AUTO_LOAD,
+        }
+    }
+
+    // the raw value as the module reads and writes it
+    pub fn val(&self) -> u32 {
+        match *self {
+            Register::Channel(channel) => channel as u32,
+            Register::PanId(pan_id) => pan_id as u32,
+            Register::FrameCounter(val) | Register::PairingId(val) | Register::PanaSessionLifetime(val) | Register::TransmissionTime(val) => val,
+            Register::BeaconResponse(flag) | Register::AutoReauthentication(flag) | Register::EncryptBroadcast(flag) |
+            Register::IcmpEchoReply(flag) | Register::TransmissionLimited(flag) | Register::EchoBack(flag) | Register::AutoLoad(flag) => flag as u32,
+        }
+    }
+
+    // the value in the format SKSREG expects
+    pub fn format_val(&self) -> String {
+        match self {
+            Register::PairingId(val) => format!("{:08X}", val),
+            register => format!("{:X}", register.val()),
+        }
+    }
+
+    // whether SKSREG may write this value
+    pub fn validate(&self) -> Result<(), RegisterError> {
+        match *self {
+            Register::FrameCounter(_) | Register::TransmissionLimited(_) | Register::TransmissionTime(_) => {
+                Err(RegisterError::ReadOnly(self.sreg()))
+            },
+            Register::Channel(channel) if !(CHANNEL_MIN..=CHANNEL_MAX).contains(&channel) => {
+                Err(RegisterError::OutOfRange(*self))
+            },
+            Register::PanaSessionLifetime(secs) if secs < PANA_SESSION_LIFETIME_MIN => {
+                Err(RegisterError::OutOfRange(*self))
+            },
+            _ => Ok(()),
+        }
+    }
+
+    // the register read back with ESREG
+    pub fn read(sreg: u8, val: u32) -> Result<Register, RegisterError> {
+        let invalid = || RegisterError::InvalidValue { sreg, val };
+        match sreg {
+            Sreg::CHANNEL => u8::try_from(val).map(Register::Channel).map_err(|_| invalid()),
+            Sreg::PAN_ID => u16::try_from(val).map(Register::PanId).map_err(|_| invalid()),
+            Sreg::FRAME_COUNTER => Ok(Register::FrameCounter(val)),
+            Sreg::PAIRING_ID => Ok(Register::PairingId(val)),
+            Sreg::BEACON_RESPONSE => flag(sreg, val).map(Register::BeaconResponse),
+            Sreg::PANA_SESSION_LIFETIME => Ok(Register::PanaSessionLifetime(val)),
+            Sreg::AUTO_REAUTHENTICATION => flag(sreg, val).map(Register::AutoReauthentication),
+            Sreg::ENCRYPT_BROADCAST => flag(sreg, val).map(Register::EncryptBroadcast),
+            Sreg::ICMP_ECHO_REPLY => flag(sreg, val).map(Register::IcmpEchoReply),
+            Sreg::TRANSMISSION_LIMITED => flag(sreg, val).map(Register::TransmissionLimited),
+            Sreg::TRANSMISSION_TIME => Ok(Register::TransmissionTime(val)),
+            Sreg::ECHO_BACK => flag(sreg, val).map(Register::EchoBack),
+            Sreg::AUTO_LOAD => flag(sreg, val).map(Register::AutoLoad),
+            sreg => Err(RegisterError::UnknownRegister(sreg)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        assert_eq!(Register::Channel(0x21).validate(), Ok(()));
+        assert_eq!(Register::Channel(0x20).validate(), Err(RegisterError::OutOfRange(Register::Channel(0x20))));
+        assert_eq!(Register::Channel(0x3D).validate(), Err(RegisterError::OutOfRange(Register::Channel(0x3D))));
+        assert_eq!(Register::PanaSessionLifetime(0x3B).validate(), Err(RegisterError::OutOfRange(Register::PanaSessionLifetime(0x3B))));
+        assert_eq!(Register::TransmissionTime(0).validate(), Err(RegisterError::ReadOnly(0xFD)));
+        assert_eq!(Register::EchoBack(false).validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_read() {
+        assert_eq!(Register::read(0x02, 0x21), Ok(Register::Channel(0x21)));
+        assert_eq!(Register::read(0x03, 0x8888), Ok(Register::PanId(0x8888)));
+        assert_eq!(Register::read(0xFE, 1), Ok(Register::EchoBack(true)));
+        assert_eq!(Register::read(0xFE, 2), Err(RegisterError::InvalidValue { sreg: 0xFE, val: 2 }));
+        assert_eq!(Register::read(0x02, 0x100), Err(RegisterError::InvalidValue { sreg: 0x02, val: 0x100 }));
+        assert_eq!(Register::read(0x50, 0), Err(RegisterError::UnknownRegister(0x50)));
+
+        for register in [Register::Channel(0x3C), Register::PairingId(0x0011AABB), Register::AutoLoad(true)] {
+            assert_eq!(Register::read(register.sreg(), register.val()), Ok(register));
+        }
+    }
+
+    #[test]
+    fn test_format_val() {
+        assert_eq!(Register::PairingId(0x0011AABB).format_val(), "0011AABB");
+        assert_eq!(Register::Channel(0x21).format_val(), "21");
+        assert_eq!(Register::EchoBack(true).format_val(), "1");
+    }
+}
@@ -0,0 +1,207 @@
+use crate::echonet_lite::{Eoj, EpcSuperClass, EpcNodeProfile, EpcLowVoltageSmartMeter, EpcHighVoltageSmartMeter};
+use crate::value::{self, Decoder};
+
+// who may read or write a property, as in the APPENDIX of the ECHONET Lite specification
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Access {
+    pub get: bool,
+    pub set: bool,
+    pub announce: bool,
+}
+
+const GET: Access = Access { get: true, set: false, announce: false };
+const SET: Access = Access { get: false, set: true, announce: false };
+const GET_SET: Access = Access { get: true, set: true, announce: false };
+const GET_ANNO: Access = Access { get: true, set: false, announce: true };
+const GET_SET_ANNO: Access = Access { get: true, set: true, announce: true };
+
+// number of bytes of EDT
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Size {
+    Fixed(usize),
+    UpTo(usize),
+}
+
+impl Size {
+    pub fn accepts(&self, len: usize) -> bool {
+        match *self {
+            Size::Fixed(size) => len == size,
+            Size::UpTo(size) => len <= size,
+        }
+    }
+}
+
+pub struct PropertyDef {
+    pub epc: u8,
+    pub name: &'static str,
+    pub size: Size,
+    pub access: Access,
+    pub decode: Decoder,
+}
+
+pub struct ClassDef {
+    pub class_group_code: u8,
+    pub class_code: u8,
+    pub name: &'static str,
+    pub properties: &'static [PropertyDef],
+}
+
+impl ClassDef {
+    pub fn matches(&self, eoj: Eoj) -> bool {
+        self.class_group_code == eoj.class_group_code && self.class_code == eoj.class_code
+    }
+}
+
+macro_rules! property {
+    ($epc:expr, $name:expr, $size:expr, $access:expr, $decode:expr) => {
+        PropertyDef { epc: $epc, name: $name, size: $size, access: $access, decode: $decode }
+    };
+}
+
+// properties every device object and the node profile have
+static SUPER_CLASS: &[PropertyDef] = &[
+    property!(EpcSuperClass::OPERATION_STATUS, "operation_status", Size::Fixed(1), GET_ANNO, value::decode_operation_status),
+    property!(EpcSuperClass::VERSION_INFORMATION, "version_information", Size::Fixed(4), GET, value::decode_raw),
+    property!(EpcSuperClass::MANUFACTURER_CODE, "manufacturer_code", Size::Fixed(3), GET, value::decode_raw),
+    property!(EpcSuperClass::PRODUCT_CODE, "product_code", Size::Fixed(12), GET, value::decode_raw),
+    property!(EpcSuperClass::PRODUCTION_NUMBER, "production_number", Size::Fixed(12), GET, value::decode_raw),
+    property!(EpcSuperClass::STATUS_CHANGE_ANNOUNCEMENT_PROPERTY_MAP, "status_change_announcement_property_map", Size::UpTo(17), GET, value::decode_property_map),
+    property!(EpcSuperClass::SET_PROPERTY_MAP, "set_property_map", Size::UpTo(17), GET, value::decode_property_map),
+    property!(EpcSuperClass::GET_PROPERTY_MAP, "get_property_map", Size::UpTo(17), GET, value::decode_property_map),
+];
+
+pub static CLASSES: &[ClassDef] = &[
+    ClassDef {
+        class_group_code: 0x0E,
+        class_code: 0xF0,
+        name: "node_profile",
+        properties: &[
+            property!(0xD3, "number_of_self_node_instances", Size::Fixed(3), GET, value::decode_unsigned),
+            property!(0xD4, "number_of_self_node_classes", Size::Fixed(2), GET, value::decode_unsigned),
+            property!(EpcNodeProfile::INSTANCE_LIST_NOTIFICATION, "instance_list_notification", Size::UpTo(253), GET_ANNO, value::decode_raw),
+            property!(EpcNodeProfile::SELF_NODE_INSTANCE_LIST_S, "self_node_instance_list_s", Size::UpTo(253), GET, value::decode_raw),
+            property!(0xD7, "self_node_class_list_s", Size::UpTo(17), GET, value::decode_raw),
+        ],
+    },
+    ClassDef {
+        class_group_code: 0x02,
+        class_code: 0x88,
+        name: "low_voltage_smart_meter",
+        properties: &[
+            property!(EpcLowVoltageSmartMeter::COEFFICIENT, "coefficient", Size::Fixed(4), GET, value::decode_coefficient),
+            property!(EpcLowVoltageSmartMeter::EFFECTIVE_DIGITS_OF_CUMULATIVE_ENERGY, "effective_digits_of_cumulative_energy", Size::Fixed(1), GET, value::decode_effective_digits),
+            property!(EpcLowVoltageSmartMeter::CUMULATIVE_ENERGY_NORMAL_DIRECTION, "cumulative_energy_normal_direction", Size::Fixed(4), GET, value::decode_cumulative_energy),
+            property!(EpcLowVoltageSmartMeter::CUMULATIVE_ENERGY_UNIT, "cumulative_energy_unit", Size::Fixed(1), GET, value::decode_energy_unit),
+            property!(EpcLowVoltageSmartMeter::HISTORICAL_CUMULATIVE_ENERGY_1_NORMAL_DIRECTION, "historical_cumulative_energy_1_normal_direction", Size::Fixed(194), GET, value::decode_history),
+            property!(EpcLowVoltageSmartMeter::CUMULATIVE_ENERGY_REVERSE_DIRECTION, "cumulative_energy_reverse_direction", Size::Fixed(4), GET, value::decode_cumulative_energy),
+            property!(EpcLowVoltageSmartMeter::HISTORICAL_CUMULATIVE_ENERGY_1_REVERSE_DIRECTION, "historical_cumulative_energy_1_reverse_direction", Size::Fixed(194), GET, value::decode_history),
+            property!(EpcLowVoltageSmartMeter::DAY_FOR_HISTORICAL_DATA_1, "day_for_historical_data_1", Size::Fixed(1), GET_SET, value::decode_history_day),
+            property!(EpcLowVoltageSmartMeter::INSTANTANEOUS_ENERGY, "instantaneous_energy", Size::Fixed(4), GET, value::decode_instantaneous_power),
+            property!(EpcLowVoltageSmartMeter::INSTANTANEOUS_CURRENT, "instantaneous_current", Size::Fixed(4), GET, value::decode_instantaneous_current),
+            property!(EpcLowVoltageSmartMeter::CUMULATIVE_ENERGY_FIXED_TIME_NORMAL_DIRECTION, "cumulative_energy_fixed_time_normal_direction", Size::Fixed(11), GET_ANNO, value::decode_fixed_time_reading),
+            property!(EpcLowVoltageSmartMeter::CUMULATIVE_ENERGY_FIXED_TIME_REVERSE_DIRECTION, "cumulative_energy_fixed_time_reverse_direction", Size::Fixed(11), GET_ANNO, value::decode_fixed_time_reading),
+        ],
+    },
+    ClassDef {
+        class_group_code: 0x02,
+        class_code: 0x8A,
+        name: "high_voltage_smart_meter",
+        properties: &[
+            property!(EpcHighVoltageSmartMeter::MONTHLY_MAXIMUM_DEMAND, "monthly_maximum_demand", Size::Fixed(4), GET, value::decode_unsigned),
+            property!(EpcHighVoltageSmartMeter::CUMULATIVE_MAXIMUM_DEMAND, "cumulative_maximum_demand", Size::Fixed(4), GET, value::decode_unsigned),
+            property!(EpcHighVoltageSmartMeter::DEMAND_FIXED_TIME, "demand_fixed_time", Size::Fixed(11), GET, value::decode_fixed_time_value),
+            property!(EpcHighVoltageSmartMeter::EFFECTIVE_DIGITS_OF_DEMAND, "effective_digits_of_demand", Size::Fixed(1), GET, value::decode_effective_digits),
+            property!(EpcHighVoltageSmartMeter::DEMAND_UNIT, "demand_unit", Size::Fixed(1), GET, value::decode_energy_unit),
+            property!(EpcHighVoltageSmartMeter::CUMULATIVE_REACTIVE_ENERGY_LAG_FIXED_TIME, "cumulative_reactive_energy_lag_fixed_time", Size::Fixed(11), GET, value::decode_fixed_time_value),
+            property!(EpcHighVoltageSmartMeter::COEFFICIENT, "coefficient", Size::Fixed(4), GET, value::decode_coefficient),
+            property!(EpcHighVoltageSmartMeter::CUMULATIVE_ACTIVE_ENERGY, "cumulative_active_energy", Size::Fixed(4), GET, value::decode_unsigned),
+            property!(EpcHighVoltageSmartMeter::CUMULATIVE_ACTIVE_ENERGY_UNIT, "cumulative_active_energy_unit", Size::Fixed(1), GET, value::decode_energy_unit),
+            property!(EpcHighVoltageSmartMeter::CUMULATIVE_ACTIVE_ENERGY_FIXED_TIME, "cumulative_active_energy_fixed_time", Size::Fixed(11), GET, value::decode_fixed_time_value),
+            property!(EpcHighVoltageSmartMeter::EFFECTIVE_DIGITS_OF_CUMULATIVE_ACTIVE_ENERGY, "effective_digits_of_cumulative_active_energy", Size::Fixed(1), GET, value::decode_effective_digits),
+        ],
+    },
+    ClassDef {
+        class_group_code: 0x02,
+        class_code: 0x79,
+        name: "residential_solar_power_generation",
+        properties: &[
+            property!(0xE0, "instantaneous_generation", Size::Fixed(2), GET, value::decode_unsigned),
+            property!(0xE1, "cumulative_generation", Size::Fixed(4), GET, value::decode_unsigned),
+            property!(0xE3, "cumulative_sold", Size::Fixed(4), GET, value::decode_unsigned),
+        ],
+    },
+    ClassDef {
+        class_group_code: 0x02,
+        class_code: 0x7D,
+        name: "storage_battery",
+        properties: &[
+            property!(0xA8, "ac_cumulative_charging_energy", Size::Fixed(4), GET, value::decode_unsigned),
+            property!(0xA9, "ac_cumulative_discharging_energy", Size::Fixed(4), GET, value::decode_unsigned),
+            property!(0xD3, "instantaneous_charging_discharging_power", Size::Fixed(4), GET, value::decode_signed),
+            property!(0xDA, "operation_mode", Size::Fixed(1), GET_SET_ANNO, value::decode_unsigned),
+            property!(0xE4, "remaining_capacity_3", Size::Fixed(1), GET, value::decode_unsigned),
+        ],
+    },
+    ClassDef {
+        class_group_code: 0x02,
+        class_code: 0xA1,
+        name: "ev_charger",
+        properties: &[
+            property!(0xC7, "vehicle_connection_status", Size::Fixed(1), GET_ANNO, value::decode_unsigned),
+            property!(0xD3, "instantaneous_charging_power", Size::Fixed(4), GET, value::decode_unsigned),
+            property!(0xD6, "cumulative_charging_energy", Size::Fixed(4), GET, value::decode_unsigned),
+            property!(0xD7, "cumulative_charging_energy_reset", Size::Fixed(1), SET, value::decode_unsigned),
+            property!(0xDA, "operation_mode", Size::Fixed(1), GET_SET_ANNO, value::decode_unsigned),
+        ],
+    },
+];
+
+pub fn class(eoj: Eoj) -> Option<&'static ClassDef> {
+    CLASSES.iter().find(|class| class.matches(eoj))
+}
+
+// class specific properties take precedence over the super class
+pub fn property(eoj: Eoj, epc: u8) -> Option<&'static PropertyDef> {
+    let class = class(eoj)?;
+    class.properties.iter()
+        .chain(SUPER_CLASS.iter())
+        .find(|property| property.epc == epc)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::echonet_lite::{EOJ_HOUSING_LOW_VOLTAGE_SMART_METER, EOJ_MANAGEMENT_CONTROLLER, EOJ_NODE_PROFILE};
+
+    #[test]
+    fn test_property() {
+        assert_eq!(class(EOJ_NODE_PROFILE).map(|class| class.name), Some("node_profile"));
+        assert_eq!(property(EOJ_HOUSING_LOW_VOLTAGE_SMART_METER, 0xE5).map(|property| property.access), Some(GET_SET));
+        assert_eq!(property(EOJ_HOUSING_LOW_VOLTAGE_SMART_METER, 0x80).map(|property| property.name), Some("operation_status"));
+
+        // the same EPC means different things in different classes
+        let battery = Eoj { class_group_code: 0x02, class_code: 0x7D, instance_code: 0x01 };
+        assert_eq!(property(battery, 0xD3).map(|property| property.name), Some("instantaneous_charging_discharging_power"));
+        assert_eq!(property(EOJ_HOUSING_LOW_VOLTAGE_SMART_METER, 0xD3).map(|property| property.name), Some("coefficient"));
+
+        assert!(property(EOJ_MANAGEMENT_CONTROLLER, 0x80).is_none());
+        assert!(property(EOJ_HOUSING_LOW_VOLTAGE_SMART_METER, 0xF0).is_none());
+    }
+
+    #[test]
+    fn test_no_duplicates() {
+        for class in CLASSES {
+            for (i, property) in class.properties.iter().enumerate() {
+                assert!(class.properties[i + 1..].iter().all(|other| other.epc != property.epc), "{} has {:#x} twice", class.name, property.epc);
+            }
+        }
+    }
+
+    #[test]
+    fn test_size() {
+        assert!(Size::Fixed(4).accepts(4));
+        assert!(!Size::Fixed(4).accepts(3));
+        assert!(Size::UpTo(17).accepts(0));
+        assert!(!Size::UpTo(17).accepts(18));
+    }
+}
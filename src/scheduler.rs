@@ -0,0 +1,225 @@
+use std::time::Duration;
+
+// when a task is run again after it succeeded
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Timing {
+    Every(Duration),
+    // once in each `period` of the wall clock, `delay` after it starts (e.g. EA/EB after each half hour)
+    Aligned { period: Duration, delay: Duration },
+}
+
+impl Timing {
+    // how often the task runs while it succeeds
+    pub fn period(&self) -> Duration {
+        match *self {
+            Timing::Every(interval) => interval,
+            Timing::Aligned { period, .. } => period,
+        }
+    }
+}
+
+// polls of the same interval are spread by up to 10% of it, so they do not keep colliding
+const JITTER_PERCENT: i64 = 10;
+// the first retry of an aligned task, doubled on each failure
+const RETRY_SECS: i64 = 60;
+const MAX_BACKOFF_SECS: i64 = 30 * 60;
+
+#[derive(Debug)]
+struct Entry<T> {
+    task: T,
+    timing: Timing,
+    // 0 first
+    priority: u8,
+    next: i64,
+    failures: u32,
+}
+
+// tasks with their own interval and priority, times are unix seconds
+#[derive(Debug)]
+pub struct Scheduler<T> {
+    entries: Vec<Entry<T>>,
+    // xorshift, good enough for jitter
+    state: u64,
+}
+
+impl<T: Copy + PartialEq> Scheduler<T> {
+    pub fn new(seed: u64) -> Self {
+        Scheduler {
+            entries: vec![],
+            state: seed | 1,
+        }
+    }
+
+    // due at once
+    pub fn add(&mut self, task: T, timing: Timing, priority: u8, now: i64) {
+        self.entries.push(Entry {
+            task,
+            timing,
+            priority,
+            next: now,
+            failures: 0,
+        });
+    }
+
+    // tasks to run now, the highest priority first
+    pub fn due(&self, now: i64) -> Vec<T> {
+        let mut due: Vec<&Entry<T>> = self.entries.iter()
+            .filter(|entry| entry.next <= now)
+            .collect();
+        due.sort_by_key(|entry| (entry.priority, entry.next));
+        due.into_iter().map(|entry| entry.task).collect()
+    }
+
+    // make the tasks up to `priority` due now, except those backing off
+    pub fn hurry(&mut self, priority: u8, now: i64) {
+        for entry in &mut self.entries {
+            if entry.priority <= priority && entry.failures == 0 {
+                entry.next = entry.next.min(now);
+            }
+        }
+    }
+
+    pub fn next_due(&self) -> Option<i64> {
+        self.entries.iter().map(|entry| entry.next).min()
+    }
+
+    pub fn succeeded(&mut self, task: T, now: i64) {
+        let Some(i) = self.position(task) else { return };
+        let next = match self.entries[i].timing {
+            Timing::Every(interval) => {
+                let interval = interval.as_secs() as i64;
+                let span = interval * JITTER_PERCENT / 100;
+                now + interval + self.jitter(span)
+            },
+            Timing::Aligned { period, delay } => {
+                let (period, delay) = (period.as_secs() as i64, delay.as_secs() as i64);
+                // never early, the data may not be there yet
+                let span = delay * JITTER_PERCENT / 100;
+                let start = (now - delay).div_euclid(period) * period;
+                start + period + delay + self.jitter(span).abs()
+            },
+        };
+        let entry = &mut self.entries[i];
+        entry.next = next;
+        entry.failures = 0;
+    }
+
+    // retried with exponential backoff
+    pub fn failed(&mut self, task: T, now: i64) {
+        let Some(i) = self.position(task) else { return };
+        let entry = &mut self.entries[i];
+        entry.failures += 1;
+        let base = match entry.timing {
+            Timing::Every(interval) => interval.as_secs() as i64,
+            Timing::Aligned { .. } => RETRY_SECS,
+        };
+        let backoff = base.saturating_mul(1 << (entry.failures - 1).min(16)).min(MAX_BACKOFF_SECS.max(base));
+        entry.next = now + backoff;
+    }
+
+    fn position(&self, task: T) -> Option<usize> {
+        self.entries.iter().position(|entry| entry.task == task)
+    }
+
+    // in -span..=span
+    fn jitter(&mut self, span: i64) -> i64 {
+        if span <= 0 {
+            return 0;
+        }
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        (self.state % (2 * span as u64 + 1)) as i64 - span
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: i64 = 1_700_000_000;
+
+    fn scheduler() -> Scheduler<u8> {
+        let mut scheduler = Scheduler::new(42);
+        scheduler.add(0xE0, Timing::Every(Duration::from_secs(300)), 2, NOW);
+        scheduler.add(0xE7, Timing::Every(Duration::from_secs(10)), 0, NOW);
+        scheduler.add(0xE8, Timing::Every(Duration::from_secs(30)), 1, NOW);
+        scheduler
+    }
+
+    #[test]
+    fn test_due() {
+        let mut scheduler = scheduler();
+        assert_eq!(scheduler.due(NOW), vec![0xE7, 0xE8, 0xE0]);
+
+        for epc in [0xE7, 0xE8, 0xE0] {
+            scheduler.succeeded(epc, NOW);
+        }
+        assert!(scheduler.due(NOW + 8).is_empty());
+        assert_eq!(scheduler.due(NOW + 11), vec![0xE7]);
+        assert_eq!(scheduler.due(NOW + 33), vec![0xE7, 0xE8]);
+        assert_eq!(scheduler.due(NOW + 330), vec![0xE7, 0xE8, 0xE0]);
+    }
+
+    #[test]
+    fn test_jitter() {
+        let mut scheduler = scheduler();
+        for _ in 0..100 {
+            scheduler.succeeded(0xE0, NOW);
+            let next = scheduler.entries[0].next;
+            assert!((NOW + 270..=NOW + 330).contains(&next));
+        }
+    }
+
+    #[test]
+    fn test_aligned() {
+        let mut scheduler = Scheduler::new(1);
+        let timing = Timing::Aligned { period: Duration::from_secs(1800), delay: Duration::from_secs(300) };
+        scheduler.add((), timing, 0, NOW);
+        assert_eq!(scheduler.due(NOW), vec![()]);
+
+        // 22:13:20 UTC, the next one is after 22:30 + 5 min
+        scheduler.succeeded((), NOW);
+        let next = scheduler.next_due().unwrap();
+        assert!((1_700_001_300..=1_700_001_330).contains(&next));
+
+        // 22:32 is still in the slot of 22:00
+        scheduler.succeeded((), 1_700_001_120);
+        assert!((1_700_001_300..=1_700_001_330).contains(&scheduler.next_due().unwrap()));
+    }
+
+    #[test]
+    fn test_backoff() {
+        let mut scheduler = scheduler();
+        scheduler.failed(0xE7, NOW);
+        assert_eq!(scheduler.entries[1].next, NOW + 10);
+        scheduler.failed(0xE7, NOW);
+        assert_eq!(scheduler.entries[1].next, NOW + 20);
+        scheduler.failed(0xE7, NOW);
+        assert_eq!(scheduler.entries[1].next, NOW + 40);
+        for _ in 0..20 {
+            scheduler.failed(0xE7, NOW);
+        }
+        assert_eq!(scheduler.entries[1].next, NOW + MAX_BACKOFF_SECS);
+
+        // other properties are not affected
+        assert_eq!(scheduler.due(NOW), vec![0xE8, 0xE0]);
+
+        scheduler.succeeded(0xE7, NOW);
+        assert_eq!(scheduler.entries[1].failures, 0);
+    }
+
+    #[test]
+    fn test_hurry() {
+        let mut scheduler = scheduler();
+        for epc in [0xE7, 0xE8, 0xE0] {
+            scheduler.succeeded(epc, NOW);
+        }
+        scheduler.hurry(1, NOW + 1);
+        assert_eq!(scheduler.due(NOW + 1), vec![0xE7, 0xE8]);
+
+        scheduler.failed(0xE7, NOW + 1);
+        scheduler.hurry(0, NOW + 2);
+        assert_eq!(scheduler.due(NOW + 2), vec![0xE8]);
+    }
+}
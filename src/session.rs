@@ -0,0 +1,485 @@
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::sync::mpsc::{Receiver, RecvTimeoutError, TryRecvError};
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use log::{debug, info, warn};
+use prometheus_exporter::prometheus::HistogramVec;
+
+use crate::UartWriter;
+use crate::airtime::{self, AirtimeBudget};
+use crate::command::{self, Command};
+use crate::parser::{Response, IpAddr, UdpPayload, Event, SendResult};
+use crate::echonet_lite::{EchonetLite, EHd, EHD1_ECHONET_LITE, EHD2_FORMAT1, EData, EDataFormat1, Eoj, EOJ_MANAGEMENT_CONTROLLER, EDataProperty, Esv};
+
+// the smartmeter may take a while to answer, but it must not block us forever
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(20);
+
+pub fn get_property(epc: u8) -> EDataProperty {
+    EDataProperty {
+        epc,
+        pdc: 0x00,
+        edt: Bytes::new(),
+    }
+}
+
+// errors after which the session can not be used anymore, including the end of the PANA session
+pub fn is_fatal(e: &(dyn Error + 'static)) -> bool {
+    e.is::<io::Error>() || matches!(e.downcast_ref::<RecvTimeoutError>(), Some(RecvTimeoutError::Disconnected))
+}
+
+// the request was held back by the transmission time limit, it is due again once sending is allowed
+pub fn is_suspended(e: &(dyn Error + 'static)) -> bool {
+    e.is::<SendSuspended>()
+}
+
+fn frame_len(frame: &EchonetLite) -> usize {
+    let bytes: Bytes = frame.clone().into();
+    bytes.len()
+}
+
+// a request was not sent to stay within the transmission time limit. Not fatal, it passes.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SendSuspended {
+    // by the module, between EVENT 32 and EVENT 33
+    Limited,
+    // by our own estimate
+    Budget,
+}
+
+impl fmt::Display for SendSuspended {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SendSuspended::Limited => write!(f, "sending is suspended by the transmission time limit"),
+            SendSuspended::Budget => write!(f, "sending is held back to stay within the transmission time budget"),
+        }
+    }
+}
+
+impl Error for SendSuspended {}
+
+// where the commands to the module are written, the UART
+pub trait CommandWriter {
+    fn send_command(&mut self, cmd: Command) -> Result<(), Box<dyn Error>>;
+}
+
+// a joined PANA session with the smartmeter
+pub struct Session<W = UartWriter> {
+    pub writer: W,
+    pub receiver: Receiver<Response>,
+    pub ipaddr: IpAddr,
+    tid: u16,
+    notifications: Vec<EDataFormat1>,
+    // INFC_Res, sent once no request is waiting for the result of its own SKSENDTO
+    confirmations: VecDeque<EchonetLite>,
+    airtime: AirtimeBudget,
+    transmission_limited: bool,
+    // by operation, see ReaderMetrics
+    round_trip: HistogramVec,
+}
+
+impl<W: CommandWriter> Session<W> {
+    pub fn new(writer: W, receiver: Receiver<Response>, ipaddr: IpAddr, round_trip: HistogramVec) -> Session<W> {
+        Session {
+            writer,
+            receiver,
+            ipaddr,
+            tid: 0,
+            notifications: vec![],
+            confirmations: VecDeque::new(),
+            airtime: AirtimeBudget::default(),
+            transmission_limited: false,
+            round_trip,
+        }
+    }
+
+    // take over what the module has spent in the last hour (SFD)
+    pub fn record_airtime(&mut self, spent: Duration) {
+        self.airtime.record(Instant::now(), spent);
+    }
+
+    pub fn airtime_remaining(&mut self) -> Duration {
+        self.airtime.remaining(Instant::now())
+    }
+
+    pub fn is_transmission_limited(&self) -> bool {
+        self.transmission_limited
+    }
+
+    // whether a request of `len` bytes may be sent now
+    pub fn can_send(&mut self, len: usize) -> Result<(), SendSuspended> {
+        if self.transmission_limited {
+            return Err(SendSuspended::Limited);
+        }
+        if !self.airtime.allows(Instant::now(), airtime::airtime(len)) {
+            return Err(SendSuspended::Budget);
+        }
+        Ok(())
+    }
+
+    fn send_frame(&mut self, frame: EchonetLite) -> Result<(), Box<dyn Error>> {
+        let len = frame_len(&frame);
+        self.writer.send_command(Command::SendEchonetLite { ipaddr: &self.ipaddr, frame })?;
+        self.airtime.record(Instant::now(), airtime::airtime(len));
+        Ok(())
+    }
+
+    // send a request to an object of the smartmeter and wait for the response with the same TID.
+    // Notifications received in the meantime are kept for `take_notifications()`.
+    pub fn request(&mut self, deoj: Eoj, esv: Esv, props: Vec<EDataProperty>) -> Result<EDataFormat1, Box<dyn Error>> {
+        self.tid = self.tid.wrapping_add(1);
+        let tid = self.tid;
+
+        let frame = EchonetLite {
+            ehd: EHd {
+                ehd1: EHD1_ECHONET_LITE,
+                ehd2: EHD2_FORMAT1,
+                tid,
+            },
+            edata: EData::EDataFormat1(EDataFormat1 {
+                seoj: EOJ_MANAGEMENT_CONTROLLER,
+                deoj,
+                esv,
+                opc: props.len() as u8,
+                props,
+            }),
+        };
+        self.send_confirmations()?;
+        self.can_send(frame_len(&frame))?;
+        self.send_frame(frame)?;
+        let sent_at = Instant::now();
+
+        loop {
+            let r = self.receiver.recv_timeout(RESPONSE_TIMEOUT)?;
+            info!("got response {:?}", r);
+            match self.route(r)? {
+                Some(Response::SkSendTo{ result: SendResult::Success, .. }) | Some(Response::Event { event: Event::UdpSent(SendResult::Success), .. }) => {
+                    self.observe_round_trip("sendto_event", sent_at);
+                },
+                Some(Response::SkSendTo{ .. }) | Some(Response::Event { event: Event::UdpSent(_), .. }) if self.transmission_limited => {
+                    // EVENT 32 came before the result of SKSENDTO, which then failed
+                    return Err(SendSuspended::Limited.into());
+                },
+                Some(r @ Response::SkSendTo{ .. }) | Some(r @ Response::Event { event: Event::UdpSent(_), .. }) => {
+                    return Err(format!("failed to send request: {:?}", r).into());
+                },
+                Some(r @ Response::Fail { .. }) => {
+                    command::check_response(r)?;
+                },
+                Some(Response::ERxUdp {
+                    data: UdpPayload::EchonetLite(EchonetLite {
+                        ehd,
+                        edata: EData::EDataFormat1(edata),
+                    }), ..
+                }) if edata.seoj == deoj && (edata.esv.is_response() || edata.esv.is_error()) => {
+                    if ehd.tid != tid {
+                        debug!("ignore response to another request: {:?}", edata);
+                        continue;
+                    }
+                    self.observe_round_trip("sendto_response", sent_at);
+                    return Ok(edata);
+                },
+                _ => {
+                    // ignore
+                }
+            }
+        }
+    }
+
+    fn observe_round_trip(&self, operation: &str, sent_at: Instant) {
+        self.round_trip.with_label_values(&[operation]).observe(sent_at.elapsed().as_secs_f64());
+    }
+
+    // send a command to the module itself, e.g. SKINFO, and wait for its result
+    pub fn command(&mut self, cmd: Command) -> Result<Response, Box<dyn Error>> {
+        self.send_confirmations()?;
+        self.writer.send_command(cmd)?;
+
+        loop {
+            let r = self.receiver.recv_timeout(RESPONSE_TIMEOUT)?;
+            match self.route(r)? {
+                // late results of requests
+                Some(Response::SkSendTo { .. }) | Some(Response::Event { .. }) | Some(Response::ERxUdp { .. }) | None => {
+                },
+                Some(r) => {
+                    return Ok(command::check_response(r)?);
+                }
+            }
+        }
+    }
+
+    // route whatever the reader thread has received so far, without blocking
+    pub fn poll(&mut self) -> Result<(), Box<dyn Error>> {
+        loop {
+            match self.receiver.try_recv() {
+                Ok(r) => {
+                    self.route(r)?;
+                },
+                Err(TryRecvError::Empty) => return self.send_confirmations(),
+                Err(TryRecvError::Disconnected) => return Err(RecvTimeoutError::Disconnected.into()),
+            }
+        }
+    }
+
+    pub fn take_notifications(&mut self) -> Vec<EDataFormat1> {
+        std::mem::take(&mut self.notifications)
+    }
+
+    // keep notifications (INF / INFC) away from the response of the current request
+    fn route(&mut self, r: Response) -> Result<Option<Response>, Box<dyn Error>> {
+        match r {
+            Response::ERxUdp {
+                data: UdpPayload::EchonetLite(EchonetLite {
+                    ehd,
+                    edata: EData::EDataFormat1(edata),
+                }), ..
+            } if edata.esv.is_notification() => {
+                info!("got notification {:?}", edata);
+                if edata.esv == Esv::Infc {
+                    self.confirmations.push_back(confirmation(ehd.tid, &edata));
+                }
+                self.notifications.push(edata);
+                Ok(None)
+            },
+            Response::ERxUdp {
+                sender,
+                rport,
+                data: UdpPayload::EchonetLite(EchonetLite {
+                    edata: EData::EDataFormat2(edata),
+                    ..
+                }), ..
+            } => {
+                info!("got ECHONET Lite format 2 message from {} port {:#x}: {:?}", sender, rport, edata);
+                Ok(None)
+            },
+            Response::ERxUdp {
+                sender,
+                rport,
+                data: UdpPayload::Other(data), ..
+            } => {
+                debug!("got non ECHONET Lite payload from {} port {:#x}: {:?}", sender, rport, data);
+                Ok(None)
+            },
+            Response::Event { event, sender } => {
+                match event {
+                    // the result of SKSENDTO when echo back is off
+                    Event::UdpSent(_) => Ok(Some(Response::Event { event, sender })),
+                    // the session is gone, the smartmeter has to be joined again
+                    Event::PanaConnectFailed | Event::PanaSessionTerminationRequested | Event::PanaSessionTerminated | Event::PanaSessionTerminationTimedOut | Event::PanaSessionExpired => {
+                        Err(io::Error::new(io::ErrorKind::ConnectionAborted, event.to_string()).into())
+                    },
+                    Event::TransmissionLimited => {
+                        warn!("event from {}: {}", sender, event);
+                        self.transmission_limited = true;
+                        Ok(None)
+                    },
+                    Event::TransmissionLimitReleased => {
+                        info!("event from {}: {}", sender, event);
+                        self.transmission_limited = false;
+                        Ok(None)
+                    },
+                    Event::Unknown { .. } => {
+                        warn!("event from {}: {}", sender, event);
+                        Ok(None)
+                    },
+                    _ => {
+                        // already logged and counted by the reader thread
+                        Ok(None)
+                    }
+                }
+            },
+            r => Ok(Some(r)),
+        }
+    }
+
+    // one at a time, each waiting for the result of its own SKSENDTO
+    fn send_confirmations(&mut self) -> Result<(), Box<dyn Error>> {
+        while let Some(frame) = self.confirmations.pop_front() {
+            // the smartmeter repeats INFC which is not confirmed
+            if self.transmission_limited {
+                warn!("can not confirm INFC while the transmission is limited");
+                self.confirmations.clear();
+                return Ok(());
+            }
+            self.send_frame(frame)?;
+
+            loop {
+                let r = self.receiver.recv_timeout(RESPONSE_TIMEOUT)?;
+                match self.route(r)? {
+                    Some(Response::SkSendTo { result: SendResult::Success, .. }) | Some(Response::Event { event: Event::UdpSent(SendResult::Success), .. }) => break,
+                    Some(r @ Response::SkSendTo { .. }) | Some(r @ Response::Event { event: Event::UdpSent(_), .. }) | Some(r @ Response::Fail { .. }) => {
+                        warn!("failed to confirm INFC: {:?}", r);
+                        break;
+                    },
+                    _ => {
+                        // ignore
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// INFC must be answered with INFC_Res, which echoes the EPCs without data
+fn confirmation(tid: u16, notification: &EDataFormat1) -> EchonetLite {
+    EchonetLite {
+        ehd: EHd {
+            ehd1: EHD1_ECHONET_LITE,
+            ehd2: EHD2_FORMAT1,
+            tid,
+        },
+        edata: EData::EDataFormat1(EDataFormat1 {
+            seoj: notification.deoj,
+            deoj: notification.seoj,
+            esv: Esv::InfcRes,
+            opc: notification.opc,
+            props: notification.props.iter().map(|prop| get_property(prop.epc)).collect(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::{channel, Sender};
+    use prometheus_exporter::prometheus::HistogramOpts;
+    use crate::echonet_lite::{EOJ_HOUSING_LOW_VOLTAGE_SMART_METER, EOJ_NODE_PROFILE, EpcLowVoltageSmartMeter};
+
+    const METER: &str = "FE80:0000:0000:0000:021C:6400:030C:12A4";
+
+    // the module and the smartmeter: what follows each frame sent
+    struct Module {
+        sent: Vec<EchonetLite>,
+        replies: VecDeque<Vec<Response>>,
+        sender: Sender<Response>,
+    }
+
+    impl CommandWriter for Module {
+        fn send_command(&mut self, cmd: Command) -> Result<(), Box<dyn Error>> {
+            if let Command::SendEchonetLite { frame, .. } = cmd {
+                self.sent.push(frame);
+            }
+            for r in self.replies.pop_front().unwrap_or_default() {
+                self.sender.send(r).unwrap();
+            }
+            Ok(())
+        }
+    }
+
+    fn session(replies: Vec<Vec<Response>>) -> Session<Module> {
+        let (sender, receiver) = channel();
+        let module = Module {
+            sent: vec![],
+            replies: replies.into(),
+            sender,
+        };
+        let round_trip = HistogramVec::new(HistogramOpts::new("round_trip_seconds", "Round trip"), &["operation"]).unwrap();
+        Session::new(module, receiver, METER.to_string(), round_trip)
+    }
+
+    fn udp_sent(result: SendResult) -> Response {
+        Response::Event {
+            event: Event::UdpSent(result),
+            sender: METER.to_string(),
+        }
+    }
+
+    fn erxudp(tid: u16, seoj: Eoj, esv: Esv, props: Vec<EDataProperty>) -> Response {
+        Response::ERxUdp {
+            sender: METER.to_string(),
+            dest: "FE80:0000:0000:0000:021D:1290:1234:5678".to_string(),
+            rport: 0x0E1A,
+            lport: 0x0E1A,
+            senderlla: "001C6400030C12A4".to_string(),
+            rssi: None,
+            secured: 1,
+            datalen: 0,
+            data: UdpPayload::EchonetLite(EchonetLite {
+                ehd: EHd {
+                    ehd1: EHD1_ECHONET_LITE,
+                    ehd2: EHD2_FORMAT1,
+                    tid,
+                },
+                edata: EData::EDataFormat1(EDataFormat1 {
+                    seoj,
+                    deoj: EOJ_MANAGEMENT_CONTROLLER,
+                    esv,
+                    opc: props.len() as u8,
+                    props,
+                }),
+            }),
+        }
+    }
+
+    fn property(epc: u8, edt: &'static [u8]) -> EDataProperty {
+        EDataProperty {
+            epc,
+            pdc: edt.len() as u8,
+            edt: Bytes::from_static(edt),
+        }
+    }
+
+    #[test]
+    fn test_notification_during_request() {
+        let power = property(EpcLowVoltageSmartMeter::INSTANTANEOUS_ENERGY, &[0x00, 0x00, 0x01, 0x2C]);
+        let mut session = session(vec![
+            // the notifications come between the Get and its response
+            vec![
+                erxudp(0x0100, EOJ_NODE_PROFILE, Esv::Inf, vec![property(0xD5, &[0x01, 0x02, 0x88, 0x01])]),
+                erxudp(0x0101, EOJ_HOUSING_LOW_VOLTAGE_SMART_METER, Esv::Infc, vec![property(0xEA, &[0x07, 0xE7, 0x04, 0x0F, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01])]),
+                udp_sent(SendResult::Success),
+                erxudp(1, EOJ_HOUSING_LOW_VOLTAGE_SMART_METER, Esv::GetRes, vec![power.clone()]),
+            ],
+            // the INFC_Res does not reach the smartmeter, which only makes it repeat the INFC
+            vec![udp_sent(SendResult::Failure)],
+        ]);
+
+        let r = session.request(EOJ_HOUSING_LOW_VOLTAGE_SMART_METER, Esv::Get, vec![get_property(EpcLowVoltageSmartMeter::INSTANTANEOUS_ENERGY)]).unwrap();
+        assert_eq!(r.esv, Esv::GetRes);
+        assert_eq!(r.props, vec![power]);
+        // nothing is sent while the Get waits for the result of its SKSENDTO
+        assert_eq!(session.writer.sent.len(), 1);
+
+        session.poll().unwrap();
+        assert_eq!(session.writer.sent.len(), 2);
+        let confirmation = &session.writer.sent[1];
+        assert_eq!(confirmation.ehd.tid, 0x0101);
+        match &confirmation.edata {
+            EData::EDataFormat1(edata) => {
+                assert_eq!(edata.esv, Esv::InfcRes);
+                assert_eq!(edata.deoj, EOJ_HOUSING_LOW_VOLTAGE_SMART_METER);
+                assert_eq!(edata.props, vec![get_property(0xEA)]);
+            },
+            edata => panic!("unexpected {:?}", edata),
+        }
+
+        let notifications = session.take_notifications();
+        assert_eq!(notifications.iter().map(|n| n.esv).collect::<Vec<_>>(), vec![Esv::Inf, Esv::Infc]);
+    }
+
+    #[test]
+    fn test_limited_during_request() {
+        let mut session = session(vec![
+            // EVENT 32 between SKSENDTO and its result
+            vec![
+                Response::Event { event: Event::TransmissionLimited, sender: METER.to_string() },
+                udp_sent(SendResult::Failure),
+            ],
+        ]);
+
+        let e = session.request(EOJ_HOUSING_LOW_VOLTAGE_SMART_METER, Esv::Get, vec![get_property(EpcLowVoltageSmartMeter::INSTANTANEOUS_ENERGY)]).unwrap_err();
+        assert_eq!(e.downcast_ref::<SendSuspended>(), Some(&SendSuspended::Limited));
+        assert!(is_suspended(e.as_ref()));
+        assert!(!is_fatal(e.as_ref()));
+        assert!(session.is_transmission_limited());
+
+        // not even sent until EVENT 33
+        let e = session.request(EOJ_HOUSING_LOW_VOLTAGE_SMART_METER, Esv::Get, vec![get_property(EpcLowVoltageSmartMeter::INSTANTANEOUS_ENERGY)]).unwrap_err();
+        assert!(is_suspended(e.as_ref()));
+        assert_eq!(session.writer.sent.len(), 1);
+    }
+}
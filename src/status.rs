@@ -0,0 +1,169 @@
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use log::warn;
+
+use crate::echonet_lite::Eoj;
+use crate::identity::MeterIdentity;
+use crate::ModuleInfo;
+
+// a client which does not finish its request in time is dropped
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+// what has been found out about the smartmeter, served as JSON on SMARTMETER_STATUS_ADDR
+#[derive(Debug, Default, Clone)]
+pub struct Status {
+    pub meter: Option<Eoj>,
+    // by map ("announce", "set", "get"), `None` if the map could not be read
+    pub property_maps: BTreeMap<&'static str, Option<Vec<u8>>>,
+    pub identity: Option<MeterIdentity>,
+    // the Wi-SUN module
+    pub module: Option<ModuleInfo>,
+}
+
+pub type SharedStatus = Arc<Mutex<Status>>;
+
+// JSON without a serializer, the status is small and flat
+fn string(s: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn optional(s: Option<&str>) -> String {
+    s.map_or_else(|| "null".to_string(), string)
+}
+
+fn identity(identity: &MeterIdentity) -> String {
+    let appendix_release = identity.appendix_release.map(String::from);
+    format!(
+        "{{\"manufacturer\":{},\"product_code\":{},\"serial_number\":{},\"appendix_release\":{},\"echonet_version\":{}}}",
+        optional(identity.manufacturer.as_deref()),
+        optional(identity.product_code.as_deref()),
+        optional(identity.serial_number.as_deref()),
+        optional(appendix_release.as_deref()),
+        optional(identity.echonet_version.as_deref()),
+    )
+}
+
+fn module(module: &ModuleInfo) -> String {
+    format!(
+        "{{\"version\":{},\"app_version\":{},\"addr64\":{},\"ipaddr\":{}}}",
+        string(&module.version),
+        string(&module.app_version),
+        string(&module.addr64),
+        string(&module.ipaddr),
+    )
+}
+
+fn epcs(epcs: &[u8]) -> String {
+    let epcs: Vec<String> = epcs.iter().map(|epc| string(&format!("0x{:02X}", epc))).collect();
+    format!("[{}]", epcs.join(","))
+}
+
+impl Status {
+    pub fn to_json(&self) -> String {
+        let meter = self.meter.map(|eoj| format!("0x{:02X}{:02X}{:02X}", eoj.class_group_code, eoj.class_code, eoj.instance_code));
+        let property_maps: Vec<String> = self.property_maps.iter()
+            .map(|(name, map)| format!("{}:{}", string(name), map.as_deref().map_or_else(|| "null".to_string(), epcs)))
+            .collect();
+        let identity = self.identity.as_ref().map_or_else(|| "null".to_string(), identity);
+        let module = self.module.as_ref().map_or_else(|| "null".to_string(), module);
+        format!("{{\"meter\":{},\"property_maps\":{{{}}},\"identity\":{},\"module\":{}}}", optional(meter.as_deref()), property_maps.join(","), identity, module)
+    }
+}
+
+// GET /status, anything else is not found
+fn respond(request_line: &str, status: &Status) -> String {
+    let mut parts = request_line.split_whitespace();
+    match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/status")) => {
+            let body = status.to_json();
+            format!("HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body)
+        },
+        _ => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+    }
+}
+
+fn handle(stream: TcpStream, status: &SharedStatus) -> io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // the headers are not used, read up to the empty line
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let response = respond(&request_line, &status.lock().expect("failed to acuire lock"));
+    (&stream).write_all(response.as_bytes())
+}
+
+pub fn serve(addr: SocketAddr, status: SharedStatus) -> io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            if let Err(e) = stream.and_then(|stream| handle(stream, &status)) {
+                warn!("failed to serve the status: {:?}", e);
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::echonet_lite::EOJ_HOUSING_LOW_VOLTAGE_SMART_METER;
+
+    #[test]
+    fn test_to_json() {
+        let mut status = Status::default();
+        assert_eq!(status.to_json(), r#"{"meter":null,"property_maps":{},"identity":null,"module":null}"#);
+
+        status.meter = Some(EOJ_HOUSING_LOW_VOLTAGE_SMART_METER);
+        status.property_maps.insert("get", Some(vec![0x80, 0xE7]));
+        status.property_maps.insert("set", None);
+        status.identity = Some(MeterIdentity {
+            manufacturer: Some("000016".to_string()),
+            appendix_release: Some('J'),
+            ..MeterIdentity::default()
+        });
+        status.module = Some(ModuleInfo {
+            version: "1.2.10".to_string(),
+            app_version: "rev26e".to_string(),
+            addr64: "001D129012345678".to_string(),
+            ipaddr: "FE80:0000:0000:0000:021D:1290:1234:5678".to_string(),
+        });
+        assert_eq!(status.to_json(), concat!(
+            r#"{"meter":"0x028801","property_maps":{"get":["0x80","0xE7"],"set":null},"#,
+            r#""identity":{"manufacturer":"000016","product_code":null,"serial_number":null,"appendix_release":"J","echonet_version":null},"#,
+            r#""module":{"version":"1.2.10","app_version":"rev26e","addr64":"001D129012345678","ipaddr":"FE80:0000:0000:0000:021D:1290:1234:5678"}}"#,
+        ));
+
+        assert_eq!(string("a\"b\\c\n"), r#""a\"b\\c\u000a""#);
+    }
+
+    #[test]
+    fn test_respond() {
+        let status = Status::default();
+        let response = respond("GET /status HTTP/1.1\r\n", &status);
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("\r\n\r\n{\"meter\":null,\"property_maps\":{},\"identity\":null,\"module\":null}"));
+
+        assert!(respond("GET /metrics HTTP/1.1\r\n", &status).starts_with("HTTP/1.1 404"));
+        assert!(respond("", &status).starts_with("HTTP/1.1 404"));
+    }
+}
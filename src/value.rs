@@ -0,0 +1,424 @@
+use std::error::Error;
+use std::fmt;
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+
+use crate::echonet_lite::{Eoj, EDataProperty, EpcSuperClass, EpcLowVoltageSmartMeter, PropertyMap};
+use crate::history::{self, Direction, FixedTimeReading};
+use crate::registry;
+
+// value used in E2/E4 for intervals the meter has not recorded
+const HISTORY_NO_DATA: u32 = 0xFFFFFFFE;
+const HISTORY_INTERVALS_PER_DAY: usize = 48;
+// value of the T phase in E8 when the meter is single-phase two-wire
+const CURRENT_NOT_MEASURED: i16 = 0x7FFE;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Watts(pub i32);
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Amperes(pub f64);
+
+// converts counts of cumulative energy into kWh, i.e. E1 (unit) × D3 (coefficient)
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct EnergyUnit {
+    pub kwh: f64,
+}
+
+impl Default for EnergyUnit {
+    fn default() -> Self {
+        EnergyUnit {
+            kwh: 1.0,
+        }
+    }
+}
+
+// E1
+fn energy_unit_kwh(unit: u8) -> Option<f64> {
+    match unit {
+        0x00 => Some(1.0),
+        0x01 => Some(0.1),
+        0x02 => Some(0.01),
+        0x03 => Some(0.001),
+        0x04 => Some(0.0001),
+        0x0A => Some(10.0),
+        0x0B => Some(100.0),
+        0x0C => Some(1000.0),
+        0x0D => Some(10000.0),
+        _ => None,
+    }
+}
+
+fn energy_unit_code(kwh: f64) -> Option<u8> {
+    [0x00, 0x01, 0x02, 0x03, 0x04, 0x0A, 0x0B, 0x0C, 0x0D].into_iter()
+        .find(|&unit| energy_unit_kwh(unit) == Some(kwh))
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum MeterValue {
+    OperationStatus(bool),
+    PropertyMap(PropertyMap),
+    Coefficient(u32),
+    EffectiveDigits(u8),
+    CumulativeEnergyUnit(f64),
+    CumulativeEnergy {
+        direction: Direction,
+        raw: u32,
+        unit: EnergyUnit,
+    },
+    HistoricalCumulativeEnergy {
+        direction: Direction,
+        days_ago: u16,
+        // one per 30 minutes from 00:00, `None` where the meter has no data
+        kwh: Vec<Option<f64>>,
+    },
+    DayForHistoricalData(u8),
+    InstantaneousPower(Watts),
+    InstantaneousCurrent {
+        r: Amperes,
+        // `None` on single-phase two-wire meters
+        t: Option<Amperes>,
+    },
+    FixedTimeReading(FixedTimeReading),
+    // fixed-time value of another class than the low-voltage smart meter, e.g. the demand of a high-voltage one
+    FixedTimeValue {
+        at: i64,
+        raw: u32,
+    },
+    // properties without a dedicated representation
+    Unsigned(u32),
+    Signed(i32),
+    Raw(Bytes),
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ValueError {
+    UnknownProperty {
+        eoj: Eoj,
+        epc: u8,
+    },
+    InvalidLength {
+        epc: u8,
+        pdc: u8,
+    },
+    InvalidValue {
+        epc: u8,
+    },
+    NotEncodable {
+        epc: u8,
+    },
+}
+
+impl fmt::Display for ValueError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValueError::UnknownProperty { eoj, epc } => write!(f, "unknown property {:#x} of {:?}", epc, eoj),
+            ValueError::InvalidLength { epc, pdc } => write!(f, "invalid length {} of property {:#x}", pdc, epc),
+            ValueError::InvalidValue { epc } => write!(f, "invalid value of property {:#x}", epc),
+            ValueError::NotEncodable { epc } => write!(f, "property {:#x} can not be encoded", epc),
+        }
+    }
+}
+
+impl Error for ValueError {}
+
+pub type Decoder = fn(epc: u8, edt: &[u8], unit: EnergyUnit) -> Result<MeterValue, ValueError>;
+
+// decode a property of the given object as described by the registry.
+// `unit` is used for cumulative energy properties.
+pub fn decode(eoj: Eoj, prop: &EDataProperty, unit: EnergyUnit) -> Result<MeterValue, ValueError> {
+    let property = registry::property(eoj, prop.epc).ok_or(ValueError::UnknownProperty { eoj, epc: prop.epc })?;
+    if !property.size.accepts(prop.edt.len()) {
+        return Err(ValueError::InvalidLength { epc: prop.epc, pdc: prop.pdc });
+    }
+    (property.decode)(prop.epc, &prop.edt, unit)
+}
+
+// decoders referenced by the registry; the length of `edt` has already been checked
+
+pub fn decode_raw(_: u8, edt: &[u8], _: EnergyUnit) -> Result<MeterValue, ValueError> {
+    Ok(MeterValue::Raw(Bytes::copy_from_slice(edt)))
+}
+
+// big endian, up to 4 bytes
+pub fn decode_unsigned(_: u8, edt: &[u8], _: EnergyUnit) -> Result<MeterValue, ValueError> {
+    Ok(MeterValue::Unsigned(edt.iter().fold(0, |value, &b| value << 8 | b as u32)))
+}
+
+pub fn decode_signed(epc: u8, mut edt: &[u8], _: EnergyUnit) -> Result<MeterValue, ValueError> {
+    let value = match edt.len() {
+        1 => edt.get_i8() as i32,
+        2 => edt.get_i16() as i32,
+        4 => edt.get_i32(),
+        _ => return Err(ValueError::InvalidLength { epc, pdc: edt.len() as u8 }),
+    };
+    Ok(MeterValue::Signed(value))
+}
+
+pub fn decode_operation_status(epc: u8, edt: &[u8], _: EnergyUnit) -> Result<MeterValue, ValueError> {
+    match edt[0] {
+        0x30 => Ok(MeterValue::OperationStatus(true)),
+        0x31 => Ok(MeterValue::OperationStatus(false)),
+        _ => Err(ValueError::InvalidValue { epc }),
+    }
+}
+
+pub fn decode_property_map(epc: u8, edt: &[u8], _: EnergyUnit) -> Result<MeterValue, ValueError> {
+    PropertyMap::decode(edt).map(MeterValue::PropertyMap).ok_or(ValueError::InvalidLength { epc, pdc: edt.len() as u8 })
+}
+
+pub fn decode_coefficient(_: u8, mut edt: &[u8], _: EnergyUnit) -> Result<MeterValue, ValueError> {
+    Ok(MeterValue::Coefficient(edt.get_u32()))
+}
+
+pub fn decode_effective_digits(_: u8, edt: &[u8], _: EnergyUnit) -> Result<MeterValue, ValueError> {
+    Ok(MeterValue::EffectiveDigits(edt[0]))
+}
+
+pub fn decode_energy_unit(epc: u8, edt: &[u8], _: EnergyUnit) -> Result<MeterValue, ValueError> {
+    energy_unit_kwh(edt[0]).map(MeterValue::CumulativeEnergyUnit).ok_or(ValueError::InvalidValue { epc })
+}
+
+pub fn decode_cumulative_energy(epc: u8, mut edt: &[u8], unit: EnergyUnit) -> Result<MeterValue, ValueError> {
+    let direction = if epc == EpcLowVoltageSmartMeter::CUMULATIVE_ENERGY_NORMAL_DIRECTION { Direction::Normal } else { Direction::Reverse };
+    Ok(MeterValue::CumulativeEnergy {
+        direction,
+        raw: edt.get_u32(),
+        unit,
+    })
+}
+
+pub fn decode_history(epc: u8, mut edt: &[u8], unit: EnergyUnit) -> Result<MeterValue, ValueError> {
+    let direction = if epc == EpcLowVoltageSmartMeter::HISTORICAL_CUMULATIVE_ENERGY_1_NORMAL_DIRECTION { Direction::Normal } else { Direction::Reverse };
+    let days_ago = edt.get_u16();
+    let kwh = (0..HISTORY_INTERVALS_PER_DAY)
+        .map(|_| edt.get_u32())
+        .map(|raw| (raw != HISTORY_NO_DATA).then_some(raw as f64 * unit.kwh))
+        .collect();
+    Ok(MeterValue::HistoricalCumulativeEnergy {
+        direction,
+        days_ago,
+        kwh,
+    })
+}
+
+pub fn decode_history_day(_: u8, edt: &[u8], _: EnergyUnit) -> Result<MeterValue, ValueError> {
+    Ok(MeterValue::DayForHistoricalData(edt[0]))
+}
+
+pub fn decode_instantaneous_power(_: u8, mut edt: &[u8], _: EnergyUnit) -> Result<MeterValue, ValueError> {
+    Ok(MeterValue::InstantaneousPower(Watts(edt.get_i32())))
+}
+
+pub fn decode_instantaneous_current(_: u8, mut edt: &[u8], _: EnergyUnit) -> Result<MeterValue, ValueError> {
+    let r = edt.get_i16();
+    let t = edt.get_i16();
+    Ok(MeterValue::InstantaneousCurrent {
+        r: Amperes(r as f64 / 10.0),
+        t: (t != CURRENT_NOT_MEASURED).then(|| Amperes(t as f64 / 10.0)),
+    })
+}
+
+pub fn decode_fixed_time_reading(epc: u8, edt: &[u8], unit: EnergyUnit) -> Result<MeterValue, ValueError> {
+    let direction = if epc == EpcLowVoltageSmartMeter::CUMULATIVE_ENERGY_FIXED_TIME_NORMAL_DIRECTION { Direction::Normal } else { Direction::Reverse };
+    let at = history::meter_time_to_unix(&edt[..7]).ok_or(ValueError::InvalidValue { epc })?;
+    let raw = (&edt[7..]).get_u32();
+    Ok(MeterValue::FixedTimeReading(FixedTimeReading {
+        direction,
+        at,
+        kwh: raw as f64 * unit.kwh,
+    }))
+}
+
+// date and time (7 bytes) followed by a 4 bytes value
+pub fn decode_fixed_time_value(epc: u8, edt: &[u8], _: EnergyUnit) -> Result<MeterValue, ValueError> {
+    let at = history::meter_time_to_unix(&edt[..7]).ok_or(ValueError::InvalidValue { epc })?;
+    Ok(MeterValue::FixedTimeValue {
+        at,
+        raw: (&edt[7..]).get_u32(),
+    })
+}
+
+impl MeterValue {
+    // the property to send with Set, or to compare with what the meter returned
+    pub fn encode(&self) -> Result<EDataProperty, ValueError> {
+        let mut edt = BytesMut::new();
+        let epc = match self {
+            MeterValue::OperationStatus(on) => {
+                edt.put_u8(if *on { 0x30 } else { 0x31 });
+                EpcSuperClass::OPERATION_STATUS
+            },
+            MeterValue::Coefficient(coefficient) => {
+                edt.put_u32(*coefficient);
+                EpcLowVoltageSmartMeter::COEFFICIENT
+            },
+            MeterValue::EffectiveDigits(digits) => {
+                edt.put_u8(*digits);
+                EpcLowVoltageSmartMeter::EFFECTIVE_DIGITS_OF_CUMULATIVE_ENERGY
+            },
+            MeterValue::CumulativeEnergyUnit(kwh) => {
+                let epc = EpcLowVoltageSmartMeter::CUMULATIVE_ENERGY_UNIT;
+                edt.put_u8(energy_unit_code(*kwh).ok_or(ValueError::InvalidValue { epc })?);
+                epc
+            },
+            MeterValue::CumulativeEnergy { direction, raw, .. } => {
+                edt.put_u32(*raw);
+                match direction {
+                    Direction::Normal => EpcLowVoltageSmartMeter::CUMULATIVE_ENERGY_NORMAL_DIRECTION,
+                    Direction::Reverse => EpcLowVoltageSmartMeter::CUMULATIVE_ENERGY_REVERSE_DIRECTION,
+                }
+            },
+            MeterValue::DayForHistoricalData(days_ago) => {
+                let epc = EpcLowVoltageSmartMeter::DAY_FOR_HISTORICAL_DATA_1;
+                if *days_ago as i64 > history::HISTORY_MAX_DAYS {
+                    return Err(ValueError::InvalidValue { epc });
+                }
+                edt.put_u8(*days_ago);
+                epc
+            },
+            MeterValue::InstantaneousPower(Watts(watts)) => {
+                edt.put_i32(*watts);
+                EpcLowVoltageSmartMeter::INSTANTANEOUS_ENERGY
+            },
+            MeterValue::PropertyMap(_) => {
+                return Err(ValueError::NotEncodable { epc: EpcSuperClass::GET_PROPERTY_MAP });
+            },
+            MeterValue::HistoricalCumulativeEnergy { .. } => {
+                return Err(ValueError::NotEncodable { epc: EpcLowVoltageSmartMeter::HISTORICAL_CUMULATIVE_ENERGY_1_NORMAL_DIRECTION });
+            },
+            MeterValue::InstantaneousCurrent { .. } => {
+                return Err(ValueError::NotEncodable { epc: EpcLowVoltageSmartMeter::INSTANTANEOUS_CURRENT });
+            },
+            MeterValue::FixedTimeReading(_) => {
+                return Err(ValueError::NotEncodable { epc: EpcLowVoltageSmartMeter::CUMULATIVE_ENERGY_FIXED_TIME_NORMAL_DIRECTION });
+            },
+            MeterValue::FixedTimeValue { .. } => {
+                return Err(ValueError::NotEncodable { epc: 0x00 });
+            },
+            // the EPC is not known without the registry
+            MeterValue::Unsigned(_) | MeterValue::Signed(_) | MeterValue::Raw(_) => {
+                return Err(ValueError::NotEncodable { epc: 0x00 });
+            },
+        };
+
+        let edt: Bytes = edt.freeze();
+        Ok(EDataProperty {
+            epc,
+            pdc: edt.len() as u8,
+            edt,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::echonet_lite::{EOJ_HOUSING_LOW_VOLTAGE_SMART_METER, EOJ_HOUSING_HIGH_VOLTAGE_SMART_METER};
+
+    // 2023-04-15 00:00:00 JST
+    const DAY_START: i64 = 1681484400;
+
+    fn prop(epc: u8, edt: &[u8]) -> EDataProperty {
+        EDataProperty {
+            epc,
+            pdc: edt.len() as u8,
+            edt: Bytes::copy_from_slice(edt),
+        }
+    }
+
+    fn decode_meter(epc: u8, edt: &[u8]) -> Result<MeterValue, ValueError> {
+        decode(EOJ_HOUSING_LOW_VOLTAGE_SMART_METER, &prop(epc, edt), EnergyUnit { kwh: 0.1 })
+    }
+
+    #[test]
+    fn test_decode_instantaneous_power() {
+        assert_eq!(decode_meter(0xE7, b"\x00\x00\x01\xa8"), Ok(MeterValue::InstantaneousPower(Watts(424))));
+        assert_eq!(decode_meter(0xE7, b"\xff\xff\xff\x9c"), Ok(MeterValue::InstantaneousPower(Watts(-100))));
+        assert_eq!(decode_meter(0xE7, b"\x00\x01\xa8"), Err(ValueError::InvalidLength { epc: 0xE7, pdc: 3 }));
+    }
+
+    #[test]
+    fn test_decode_instantaneous_current() {
+        assert_eq!(decode_meter(0xE8, b"\x00\x1e\x7f\xfe"), Ok(MeterValue::InstantaneousCurrent { r: Amperes(3.0), t: None }));
+        assert_eq!(decode_meter(0xE8, b"\x00\x1e\x00\x14"), Ok(MeterValue::InstantaneousCurrent { r: Amperes(3.0), t: Some(Amperes(2.0)) }));
+    }
+
+    #[test]
+    fn test_decode_cumulative_energy() {
+        assert_eq!(decode_meter(0xE0, b"\x00\x00\x12\x34"), Ok(MeterValue::CumulativeEnergy { direction: Direction::Normal, raw: 0x1234, unit: EnergyUnit { kwh: 0.1 } }));
+
+        assert_eq!(decode_meter(0xE1, b"\x01"), Ok(MeterValue::CumulativeEnergyUnit(0.1)));
+        assert_eq!(decode_meter(0xE1, b"\x05"), Err(ValueError::InvalidValue { epc: 0xE1 }));
+    }
+
+    #[test]
+    fn test_decode_fixed_time_reading() {
+        let value = decode_meter(0xEA, b"\x07\xe7\x04\x0f\x0c\x1e\x00\x00\x00\x12\x34").unwrap();
+        assert_eq!(value, MeterValue::FixedTimeReading(FixedTimeReading {
+            direction: Direction::Normal,
+            at: DAY_START + 12 * 60 * 60 + 30 * 60,
+            kwh: 0x1234 as f64 * 0.1,
+        }));
+
+        assert_eq!(decode_meter(0xEB, b"\x07\xe7\x0d\x0f\x0c\x1e\x00\x00\x00\x12\x34"), Err(ValueError::InvalidValue { epc: 0xEB }));
+        assert_eq!(decode_meter(0xEB, b"\x07\xe7\x04\x0f"), Err(ValueError::InvalidLength { epc: 0xEB, pdc: 4 }));
+    }
+
+    #[test]
+    fn test_decode_high_voltage_smart_meter() {
+        let eoj = EOJ_HOUSING_HIGH_VOLTAGE_SMART_METER;
+        assert_eq!(decode(eoj, &prop(0xC3, b"\x07\xe7\x04\x0f\x0c\x1e\x00\x00\x00\x01\x2c"), EnergyUnit::default()), Ok(MeterValue::FixedTimeValue {
+            at: DAY_START + 12 * 60 * 60 + 30 * 60,
+            raw: 300,
+        }));
+        assert_eq!(decode(eoj, &prop(0xD3, b"\x00\x00\x00\x0a"), EnergyUnit::default()), Ok(MeterValue::Coefficient(10)));
+        assert_eq!(decode(eoj, &prop(0xE7, b"\x00\x00\x00\x0a"), EnergyUnit::default()), Err(ValueError::UnknownProperty { eoj, epc: 0xE7 }));
+    }
+
+    #[test]
+    fn test_decode_history() {
+        let mut edt = vec![0x00, 0x01];
+        for i in 0..48u32 {
+            edt.extend_from_slice(&(if i < 2 { i } else { HISTORY_NO_DATA }).to_be_bytes());
+        }
+
+        match decode_meter(0xE4, &edt).unwrap() {
+            MeterValue::HistoricalCumulativeEnergy { direction, days_ago, kwh } => {
+                assert_eq!(direction, Direction::Reverse);
+                assert_eq!(days_ago, 1);
+                assert_eq!(kwh.len(), 48);
+                assert_eq!(kwh[..3], [Some(0.0), Some(0.1), None]);
+            },
+            value => panic!("unexpected value: {:?}", value),
+        }
+
+        assert_eq!(decode_meter(0xE2, &edt[1..]), Err(ValueError::InvalidLength { epc: 0xE2, pdc: 193 }));
+    }
+
+    #[test]
+    fn test_decode_unknown_property() {
+        assert_eq!(decode_meter(0xF0, b"\x00"), Err(ValueError::UnknownProperty { eoj: EOJ_HOUSING_LOW_VOLTAGE_SMART_METER, epc: 0xF0 }));
+
+        // superclass properties are known by every class, class specific ones only by their class
+        let eoj = Eoj { class_group_code: 0x02, class_code: 0x79, instance_code: 0x01 };
+        assert_eq!(decode(eoj, &prop(0x80, b"\x30"), EnergyUnit::default()), Ok(MeterValue::OperationStatus(true)));
+        assert_eq!(decode(eoj, &prop(0xE7, b"\x00\x00\x00\x01"), EnergyUnit::default()), Err(ValueError::UnknownProperty { eoj, epc: 0xE7 }));
+        assert_eq!(decode(eoj, &prop(0xE0, b"\x01\xa8"), EnergyUnit::default()), Ok(MeterValue::Unsigned(424)));
+
+        let battery = Eoj { class_group_code: 0x02, class_code: 0x7D, instance_code: 0x01 };
+        assert_eq!(decode(battery, &prop(0xD3, b"\xff\xff\xff\x9c"), EnergyUnit::default()), Ok(MeterValue::Signed(-100)));
+    }
+
+    #[test]
+    fn test_encode() {
+        assert_eq!(MeterValue::DayForHistoricalData(3).encode(), Ok(prop(0xE5, b"\x03")));
+        assert_eq!(MeterValue::DayForHistoricalData(100).encode(), Err(ValueError::InvalidValue { epc: 0xE5 }));
+        assert_eq!(MeterValue::CumulativeEnergyUnit(0.01).encode(), Ok(prop(0xE1, b"\x02")));
+        assert_eq!(MeterValue::InstantaneousPower(Watts(-100)).encode(), Ok(prop(0xE7, b"\xff\xff\xff\x9c")));
+
+        for value in [MeterValue::OperationStatus(false), MeterValue::Coefficient(10), MeterValue::InstantaneousPower(Watts(424))] {
+            let prop = value.encode().unwrap();
+            assert_eq!(decode(EOJ_HOUSING_LOW_VOLTAGE_SMART_METER, &prop, EnergyUnit::default()), Ok(value));
+        }
+    }
+}